@@ -0,0 +1,298 @@
+use bevy::{ecs::system::EntityCommands, math::*, prelude::*, utils::HashMap};
+use iyes_loopless::prelude::*;
+use rand::Rng;
+
+use crate::{
+    board::{GameBoard, Traversal},
+    enemies::{Enemy, Health},
+    player::GameSettings,
+    schedule::TIMESTEP,
+    GameRng, GameState,
+};
+
+pub struct AbilitiesPlugin;
+impl Plugin for AbilitiesPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_enter_system(GameState::RunLevel, setup_ability_gfx)
+            .add_system(spawn_ability_indicators.run_in_state(GameState::RunLevel));
+    }
+}
+
+/// Runners spawned when a splitter dies, and the fraction of a normal runner's health they get
+pub const SPLIT_CHILDREN: u32 = 2;
+pub const SPLIT_CHILD_HEALTH: f32 = 0.5;
+const SHIELD_RADIUS: f32 = 2.0;
+/// Fraction of damage to covered units that the shield takes instead
+const SHIELD_ABSORB: f32 = 0.6;
+/// Shield pool as a fraction of the carrier's own health, and how fast it refills per second
+const SHIELD_POOL: f32 = 1.5;
+const SHIELD_REGEN: f32 = 0.1;
+const HEAL_RADIUS: f32 = 2.5;
+/// Fraction of max health restored per second to units near a healer
+const HEAL_RATE: f32 = 0.06;
+
+/// Special behaviour a ground unit can spawn with
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Ability {
+    /// Breaks into runners on death
+    Splitter,
+    /// Absorbs part of the damage taken by units around it
+    Shield,
+    /// Regenerates units around it
+    Healer,
+    /// Digs through one filled cell instead of going around
+    Tunneler,
+}
+
+impl Ability {
+    pub const ALL: [Ability; 4] = [
+        Ability::Splitter,
+        Ability::Shield,
+        Ability::Healer,
+        Ability::Tunneler,
+    ];
+
+    /// Level from which spawns can roll this ability
+    pub fn min_level(&self) -> f32 {
+        match self {
+            Ability::Splitter => 5.0,
+            Ability::Shield => 15.0,
+            Ability::Healer => 25.0,
+            Ability::Tunneler => 35.0,
+        }
+    }
+
+    pub fn roll(settings: &GameSettings, level: f32, rng: &mut GameRng) -> Option<Ability> {
+        if rng.gen::<f32>() >= settings.enemy_ability_chance {
+            return None;
+        }
+        let unlocked = Ability::ALL
+            .into_iter()
+            .filter(|ability| level >= ability.min_level())
+            .collect::<Vec<_>>();
+        if unlocked.is_empty() {
+            return None;
+        }
+        Some(unlocked[rng.gen_range(0..unlocked.len())])
+    }
+
+    /// Adds the ability and any state it needs to a freshly spawned enemy
+    pub fn insert(self, ecmds: &mut EntityCommands, health: f32) {
+        ecmds.insert(self);
+        match self {
+            Ability::Shield => {
+                ecmds.insert(ShieldCarrier {
+                    pool: health * SHIELD_POOL,
+                    max: health * SHIELD_POOL,
+                });
+            }
+            Ability::Tunneler => {
+                ecmds.insert(Tunneler {
+                    tunnels: 1,
+                    inside: false,
+                });
+            }
+            Ability::Splitter | Ability::Healer => (),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct ShieldCarrier {
+    pub pool: f32,
+    max: f32,
+}
+
+#[derive(Component)]
+pub struct Tunneler {
+    pub tunnels: u32,
+    /// Currently inside a filled cell, the tunnel is spent on entry
+    inside: bool,
+}
+
+impl Tunneler {
+    pub fn traversal(&self) -> Traversal {
+        Traversal {
+            tunnels: self.tunnels,
+        }
+    }
+}
+
+/// Health as of the end of the last ability step, so shields can tell how much damage came in
+/// from any source, and healing knows where to stop
+#[derive(Component)]
+pub struct HealthTrack {
+    max: f32,
+    last: f32,
+}
+
+pub(crate) fn absorb_shield_damage(
+    mut com: Commands,
+    mut carriers: Query<(Entity, &Transform, &mut ShieldCarrier)>,
+    mut enemies: Query<(Entity, &Transform, &mut Health, Option<&mut HealthTrack>), With<Enemy>>,
+) {
+    // Each damaged unit asks the closest carrier that covers it
+    let mut requests = Vec::new();
+    for (entity, trans, health, track) in enemies.iter() {
+        let damage = match track {
+            Some(track) => track.last - health.0,
+            None => continue,
+        };
+        if damage <= 0.0 {
+            continue;
+        }
+        let carrier = carriers
+            .iter()
+            .filter(|(carrier, carrier_trans, shield)| {
+                *carrier != entity
+                    && shield.pool > 0.0
+                    && carrier_trans.translation.distance(trans.translation) < SHIELD_RADIUS
+            })
+            .map(|(carrier, carrier_trans, _)| {
+                (
+                    carrier,
+                    carrier_trans.translation.distance(trans.translation),
+                )
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((carrier, _)) = carrier {
+            requests.push((carrier, entity, damage * SHIELD_ABSORB));
+        }
+    }
+
+    // Carriers that can't cover everything asked of them share their pool proportionally.
+    // Summing in sorted order keeps this independent of query iteration order.
+    requests.sort_by(|a, b| a.2.total_cmp(&b.2));
+    let mut totals: HashMap<Entity, f32> = HashMap::default();
+    for (carrier, _, amount) in &requests {
+        *totals.entry(*carrier).or_default() += amount;
+    }
+    let ratios = totals
+        .into_iter()
+        .map(|(carrier, total)| {
+            let pool = carriers.get(carrier).map(|(_, _, s)| s.pool).unwrap_or(0.0);
+            (carrier, (pool / total).min(1.0))
+        })
+        .collect::<HashMap<_, _>>();
+    for (carrier, entity, amount) in requests {
+        let absorbed = amount * ratios[&carrier];
+        if let Ok((_, _, mut shield)) = carriers.get_mut(carrier) {
+            shield.pool -= absorbed;
+        }
+        if let Ok((_, _, mut health, _)) = enemies.get_mut(entity) {
+            health.0 += absorbed;
+        }
+    }
+
+    for (_, _, mut shield) in carriers.iter_mut() {
+        shield.pool = (shield.pool + shield.max * SHIELD_REGEN * TIMESTEP).min(shield.max);
+    }
+    for (entity, _, health, track) in enemies.iter_mut() {
+        match track {
+            Some(mut track) => track.last = health.0,
+            None => {
+                com.entity(entity).insert(HealthTrack {
+                    max: health.0,
+                    last: health.0,
+                });
+            }
+        }
+    }
+}
+
+pub(crate) fn heal_nearby(
+    healers: Query<(Entity, &Transform, &Ability)>,
+    mut enemies: Query<(Entity, &Transform, &mut Health, &mut HealthTrack), With<Enemy>>,
+) {
+    let healers = healers
+        .iter()
+        .filter(|(_, _, ability)| **ability == Ability::Healer)
+        .map(|(entity, trans, _)| (entity, trans.translation))
+        .collect::<Vec<_>>();
+    if healers.is_empty() {
+        return;
+    }
+    for (entity, trans, mut health, mut track) in enemies.iter_mut() {
+        // Healers don't stack and don't heal themselves
+        let in_range = healers.iter().any(|(healer, pos)| {
+            *healer != entity && pos.distance(trans.translation) < HEAL_RADIUS
+        });
+        if in_range && health.0 < track.max {
+            let healed = (track.max * HEAL_RATE * TIMESTEP).min(track.max - health.0);
+            health.0 += healed;
+            // Not damage, so shields shouldn't see it as a change either
+            track.last += healed;
+        }
+    }
+}
+
+pub(crate) fn update_tunnels(b: Res<GameBoard>, mut tunnelers: Query<(&Transform, &mut Tunneler)>) {
+    for (trans, mut tunneler) in tunnelers.iter_mut() {
+        let filled = b.board[b.ls_to_idx(b.ws_vec3_to_ls(trans.translation))].filled;
+        if filled && !tunneler.inside {
+            tunneler.tunnels = tunneler.tunnels.saturating_sub(1);
+            tunneler.inside = true;
+        } else if !filled {
+            tunneler.inside = false;
+        }
+    }
+}
+
+#[derive(Resource)]
+struct AbilityGfx {
+    mesh: Handle<Mesh>,
+    splitter: Handle<StandardMaterial>,
+    shield: Handle<StandardMaterial>,
+    healer: Handle<StandardMaterial>,
+    tunneler: Handle<StandardMaterial>,
+}
+
+fn setup_ability_gfx(
+    mut com: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let mut material = |color: Color| {
+        materials.add(StandardMaterial {
+            base_color: Color::BLACK,
+            emissive: color,
+            unlit: true,
+            ..default()
+        })
+    };
+    com.insert_resource(AbilityGfx {
+        mesh: meshes.add(Mesh::from(shape::Torus {
+            radius: 0.45,
+            ring_radius: 0.04,
+            ..default()
+        })),
+        splitter: material(Color::rgb(1.0, 0.8, 0.1)),
+        shield: material(Color::rgb(0.3, 0.8, 1.0)),
+        healer: material(Color::rgb(0.2, 1.0, 0.3)),
+        tunneler: material(Color::rgb(0.7, 0.4, 0.2)),
+    });
+}
+
+/// Ring around enemies with an ability, colored by ability
+fn spawn_ability_indicators(
+    mut com: Commands,
+    enemies: Query<(Entity, &Ability), Added<Ability>>,
+    gfx: Res<AbilityGfx>,
+) {
+    for (entity, ability) in enemies.iter() {
+        let material = match ability {
+            Ability::Splitter => &gfx.splitter,
+            Ability::Shield => &gfx.shield,
+            Ability::Healer => &gfx.healer,
+            Ability::Tunneler => &gfx.tunneler,
+        };
+        com.entity(entity).add_children(|parent| {
+            parent.spawn(PbrBundle {
+                mesh: gfx.mesh.clone(),
+                material: material.clone(),
+                transform: Transform::from_translation(vec3(0.0, 0.15, 0.0)),
+                ..default()
+            });
+        });
+    }
+}
@@ -1,17 +1,21 @@
-use std::time::Duration;
-
 use bevy::{math::*, prelude::*};
-use iyes_loopless::{
-    prelude::FixedTimesteps,
-    state::{CurrentState, NextState},
-};
+use iyes_loopless::state::{CurrentState, NextState};
 use rkyv::{Archive, Deserialize, Serialize};
 
 use bytecheck::CheckBytes;
+use lz4_flex::{compress_prepend_size, decompress_size_prepended};
 
 use crate::{
-    assets::ModelAssets, board::GameBoard, player::PlayerState, schedule::TIMESTEP_MILLI,
-    turrets::Turret, ui::Preferences, PausedState, RestartGame,
+    assets::ModelAssets,
+    board::GameBoard,
+    player::{GameSettings, Placement, PlayerState},
+    run::RunConfig,
+    schedule::SimSpeed,
+    tactical::PlannedActions,
+    turrets::{repair_turret, Fired, Turret, TurretHealth},
+    ui::Preferences,
+    walls::spawn_wall,
+    PausedState, RestartGame,
 };
 
 pub fn process_actions(
@@ -22,12 +26,39 @@ pub fn process_actions(
     model_assets: Res<ModelAssets>,
     mut b: ResMut<GameBoard>,
     pref: Res<Preferences>,
-    mut time_step_info: ResMut<FixedTimesteps>,
+    mut sim_speed: ResMut<SimSpeed>,
     paused_state: Res<CurrentState<PausedState>>,
     mut game_recorder: ResMut<GameRecorder>,
+    mut turrets: Query<(&Turret, &mut Transform, &mut TurretHealth)>,
+    settings: Res<GameSettings>,
+    fired: Query<(), With<Fired>>,
+    mut planned: ResMut<PlannedActions>,
 ) {
+    drop_unpaired_moves(&mut action_queue.0);
+    let paused = *paused_state == CurrentState(PausedState::Paused);
+    if paused && !game_recorder.play {
+        // Building waits for the pause to end, and isn't recorded until it happens
+        action_queue.0.retain(|action| {
+            if PlannedActions::plans(action) {
+                planned.push(*action);
+                false
+            } else if *action == Action::Undo && !planned.is_empty() {
+                planned.undo();
+                false
+            } else {
+                true
+            }
+        });
+    } else if !paused && !planned.is_empty() {
+        let mut plan = std::mem::take(&mut planned.0);
+        plan.append(&mut action_queue.0);
+        action_queue.0 = plan;
+    }
+
     if game_recorder.play {
-        while let Some((step, rec_actions)) = game_recorder.actions.0.get(game_recorder.play_head) {
+        while let Some((step, rec_actions)) =
+            game_recorder.actions.actions.get(game_recorder.play_head)
+        {
             if *step as u64 == player.step {
                 action_queue.0.push(Action::from_bytes(*rec_actions))
             } else {
@@ -45,8 +76,11 @@ pub fn process_actions(
         debug_build = true;
     }
 
+    drop_unpaired_moves(&mut action_queue.0);
+    let mut area_anchor = None;
+    let mut move_anchor = None;
     for action in action_queue.0.iter() {
-        let mut place_turret = None;
+        let mut place = Vec::new();
         match action {
             Action::Empty => continue,
             Action::BlasterUpgrade => {
@@ -71,31 +105,117 @@ pub fn process_actions(
                 }
             }
             Action::BlasterPlace(x, y) => {
-                place_turret = Some((Turret::Blaster, x, y));
+                place.push((Piece::Turret(Turret::Blaster), *x, *y));
             }
             Action::WavePlace(x, y) => {
-                place_turret = Some((Turret::Wave, x, y));
+                place.push((Piece::Turret(Turret::Wave), *x, *y));
             }
             Action::LaserPlace(x, y) => {
-                place_turret = Some((Turret::Laser, x, y));
+                place.push((Piece::Turret(Turret::Laser), *x, *y));
+            }
+            Action::EconomyPlace(x, y) => {
+                place.push((Piece::Turret(Turret::Economy), *x, *y));
+            }
+            Action::WallPlace(x, y) => {
+                place.push((Piece::Wall, *x, *y));
+            }
+            Action::AreaTo(x, y) => {
+                if let Some((piece, x0, y0)) = area_anchor {
+                    for (x, y) in area_cells((x0, y0), (*x, *y)) {
+                        place.push((piece, x, y));
+                    }
+                }
             }
             Action::SellTurret(x, y) => {
+                if !settings.allow_selling {
+                    continue;
+                }
                 let idx = b.ls_to_idx(ivec2(*x as i32, *y as i32));
+                let wall = b.board[idx].wall.is_some();
                 let turret = b.destroy(&mut com, idx);
                 if let Some(turret) = turret {
                     // Player gets back 50% of cost when selling
                     player.credits += turret.cost() / 2;
+                } else if wall {
+                    player.credits += (settings.wall_cost as f32 * settings.wall_refund) as u64;
+                }
+            }
+            Action::RepairTurret(x, y) => {
+                let idx = b.ls_to_idx(ivec2(*x as i32, *y as i32));
+                if let Some((_, entity)) = b.board[idx].turret.or(b.board[idx].wreck) {
+                    if let Ok((turret, mut trans, mut health)) = turrets.get_mut(entity) {
+                        let cost = turret.repair_cost(&health);
+                        // A wreck only comes back if its cell can be filled again
+                        if player.alive()
+                            && cost > 0
+                            && player.credits >= cost
+                            && (b.board[idx].wreck.is_none() || b.restore_wreck(idx))
+                        {
+                            player.credits -= cost;
+                            repair_turret(&mut com, entity, &mut trans, &mut health);
+                        }
+                    }
+                }
+            }
+            Action::BuyBaseShield => {
+                player.buy_base_shield(&settings);
+            }
+            // Only picks up, the turret moves with the `MoveTo` right after it
+            Action::MoveFrom(..) => (),
+            Action::MoveTo(x, y) => {
+                if let Some((from_x, from_y)) = move_anchor {
+                    let from = b.ls_to_idx(ivec2(from_x as i32, from_y as i32));
+                    let to = b.ls_to_idx(ivec2(*x as i32, *y as i32));
+                    if let Some((_, entity)) = b.board[from].turret {
+                        if let Ok((turret, mut trans, _)) = turrets.get_mut(entity) {
+                            let fee = turret.move_fee(&settings);
+                            if player.alive() && player.credits >= fee && b.move_turret(from, to) {
+                                player.credits -= fee;
+                                // Keeps its height so wrecked turrets stay sunk
+                                let pos = b.ls_to_ws_vec3(b.idx_to_ls(to));
+                                trans.translation = vec3(pos.x, trans.translation.y, pos.z);
+                                // Undo takes it back from where it is now
+                                for placement in player.placements.iter_mut() {
+                                    if placement.entity == entity {
+                                        placement.idx = to;
+                                    }
+                                }
+                            }
+                        }
+                    }
                 }
             }
+            Action::Undo if player.alive() => {
+                // Sold, moved over, fired or out of the window are all for good, so those are
+                // dropped and the newest placement left is the one taken back
+                let placements = std::mem::take(&mut player.placements);
+                player.placements = placements
+                    .into_iter()
+                    .filter(|placement| {
+                        let cell = &b.board[placement.idx];
+                        let still_there = cell.turret.map(|(_, entity)| entity)
+                            == Some(placement.entity)
+                            || cell.wall == Some(placement.entity);
+                        still_there
+                            && !fired.contains(placement.entity)
+                            && player.can_undo(placement, &settings)
+                    })
+                    .collect();
+                if let Some(placement) = player.placements.pop() {
+                    b.destroy(&mut com, placement.idx);
+                    player.credits += placement.cost;
+                }
+            }
+            // The game is over, there is nothing left to take back
+            Action::Undo => (),
             Action::GameSpeedDec => {
-                player.time_multiplier = (player.time_multiplier - 0.1).max(0.1);
-                time_step_info.single_mut().step =
-                    Duration::from_millis((TIMESTEP_MILLI as f64 / player.time_multiplier) as u64)
+                sim_speed.multiplier = (sim_speed.multiplier - 0.1).max(0.1);
             }
             Action::GameSpeedInc => {
-                player.time_multiplier = (player.time_multiplier + 0.1).min(10.0);
-                time_step_info.single_mut().step =
-                    Duration::from_millis((TIMESTEP_MILLI as f64 / player.time_multiplier) as u64)
+                sim_speed.multiplier = (sim_speed.multiplier + 0.1).min(10.0);
+            }
+            Action::GameSpeedMax => {
+                sim_speed.max_speed = !sim_speed.max_speed;
             }
             Action::GamePause => {
                 if *paused_state == CurrentState(PausedState::Paused) {
@@ -106,6 +226,7 @@ pub fn process_actions(
             }
             Action::RestartGame => {
                 **restart = true;
+                planned.clear();
             }
             Action::CheatCredits => {
                 if debug_build {
@@ -114,7 +235,7 @@ pub fn process_actions(
             }
             Action::CheatHealth => {
                 if debug_build {
-                    player.health += 1000.0;
+                    player.health += 1000;
                 }
             }
             Action::CheatLevel => {
@@ -123,37 +244,27 @@ pub fn process_actions(
                 }
             }
         }
-        if let Some((turret, x, y)) = place_turret {
-            let idx = b.ls_to_idx(ivec2(*x as i32, *y as i32));
-            if !b.board[idx].filled && idx != b.ls_to_idx(b.start) {
-                b.board[idx].filled = true; //Just temp fill so we can check
-                let possible_path = b.path(b.start, b.dest);
-                b.board[idx].filled = false; //Undo temp fill
-                if possible_path.is_some() {
-                    let cost = turret.cost();
-                    if player.credits >= cost {
-                        player.credits -= cost;
-                        let idx = b.ls_to_idx(ivec2(*x as i32, *y as i32));
-                        let pos = b.ls_to_ws_vec3(b.idx_to_ls(idx));
-                        b.board[idx].turret = Some(match turret {
-                            Turret::Blaster => {
-                                Turret::spawn_blaster_turret(&mut com, pos, &model_assets, &pref)
-                            }
-                            Turret::Laser => Turret::spawn_laser_continuous_turret(
-                                &mut com,
-                                pos,
-                                &model_assets,
-                                &pref,
-                            ),
-                            Turret::Wave => {
-                                Turret::spawn_shockwave_turret(&mut com, pos, &model_assets, &pref)
-                            }
-                        });
-
-                        b.board[idx].filled = true;
-                    }
-                }
-            }
+        // Only a placement directly before it can anchor an area
+        area_anchor = match action {
+            Action::AreaTo(..) => None,
+            _ => place.first().copied(),
+        };
+        move_anchor = match action {
+            Action::MoveFrom(x, y) => Some((*x, *y)),
+            _ => None,
+        };
+        for (piece, x, y) in place {
+            place_piece(
+                &mut com,
+                &mut b,
+                &mut player,
+                &model_assets,
+                &pref,
+                &settings,
+                piece,
+                x,
+                y,
+            );
         }
     }
 
@@ -164,6 +275,7 @@ pub fn process_actions(
                 Action::Empty
                     | Action::GameSpeedDec
                     | Action::GameSpeedInc
+                    | Action::GameSpeedMax
                     | Action::GamePause
                     | Action::RestartGame
             )
@@ -172,7 +284,7 @@ pub fn process_actions(
         for action in action_queue.iter() {
             game_recorder
                 .actions
-                .0
+                .actions
                 .push((player.step as u32, action.to_bytes()));
         }
     }
@@ -180,10 +292,149 @@ pub fn process_actions(
     action_queue.0 = Vec::new(); // Clear action queue
 }
 
+/// A `MoveFrom` only anchors the `MoveTo` queued right along with it. Either half on its own,
+/// like one left over from a pause, is dropped so it can't pair up with a cell from another input.
+fn drop_unpaired_moves(actions: &mut Vec<Action>) {
+    let paired: Vec<bool> = (0..actions.len())
+        .map(|i| match actions[i] {
+            Action::MoveFrom(..) => matches!(actions.get(i + 1), Some(Action::MoveTo(..))),
+            Action::MoveTo(..) => i > 0 && matches!(actions[i - 1], Action::MoveFrom(..)),
+            _ => true,
+        })
+        .collect();
+    let mut keep = paired.into_iter();
+    actions.retain(|_| keep.next().unwrap_or(true));
+}
+
+/// Anything that fills a single board cell
+#[derive(Clone, Copy, Debug)]
+enum Piece {
+    Turret(Turret),
+    Wall,
+}
+
+fn place_piece(
+    com: &mut Commands,
+    b: &mut GameBoard,
+    player: &mut PlayerState,
+    model_assets: &ModelAssets,
+    pref: &Preferences,
+    settings: &GameSettings,
+    piece: Piece,
+    x: u8,
+    y: u8,
+) {
+    let idx = b.ls_to_idx(ivec2(x as i32, y as i32));
+    if !b.can_fill(idx) {
+        return;
+    }
+    let cost = match piece {
+        Piece::Turret(turret) => turret.cost(),
+        Piece::Wall => settings.wall_cost,
+    };
+    if player.credits < cost {
+        return;
+    }
+    player.credits -= cost;
+    b.clear_wreck(com, idx);
+    let pos = b.ls_to_ws_vec3(b.idx_to_ls(idx));
+    let entity = match piece {
+        Piece::Turret(turret) => {
+            let (turret, entity) = match turret {
+                Turret::Blaster => Turret::spawn_blaster_turret(com, pos, model_assets, pref),
+                Turret::Laser => {
+                    Turret::spawn_laser_continuous_turret(com, pos, model_assets, pref)
+                }
+                Turret::Wave => Turret::spawn_shockwave_turret(com, pos, model_assets, pref),
+                Turret::Economy => {
+                    Turret::spawn_economy_turret(com, pos, model_assets, pref, settings)
+                }
+            };
+            b.board[idx].turret = Some((turret, entity));
+            entity
+        }
+        Piece::Wall => {
+            let entity = spawn_wall(com, pos, model_assets);
+            b.board[idx].wall = Some(entity);
+            entity
+        }
+    };
+    b.board[idx].filled = true;
+    player.placements.push(Placement {
+        step: player.step,
+        level: player.level,
+        idx,
+        entity,
+        cost,
+    });
+}
+
+/// Cells of the rectangle between two corners, starting at `from` and working outward so earlier
+/// cells get first claim on credits and on the path
+pub(crate) fn area_cells(from: (u8, u8), to: (u8, u8)) -> Vec<(u8, u8)> {
+    let span = |a: u8, b: u8| -> Vec<u8> {
+        if a <= b {
+            (a..=b).collect()
+        } else {
+            (b..=a).rev().collect()
+        }
+    };
+    let xs = span(from.0, to.0);
+    span(from.1, to.1)
+        .into_iter()
+        .flat_map(|y| xs.iter().map(move |x| (*x, y)))
+        .collect()
+}
+
+/// Bumped whenever the recording or the simulation changes in a way old replays can't follow
+const REPLAY_VERSION: u8 = 1;
+
 #[derive(Archive, Deserialize, Serialize, Clone, Eq, PartialEq, Default, Debug)]
 #[archive(compare(PartialEq))]
 #[archive_attr(derive(CheckBytes))]
-pub struct ActionRecording(Vec<(u32, [u8; 3])>);
+pub struct ActionRecording {
+    pub run: RunConfig,
+    actions: Vec<(u32, [u8; 3])>,
+}
+
+impl ActionRecording {
+    pub fn new(run: RunConfig) -> Self {
+        ActionRecording {
+            run,
+            actions: Vec::new(),
+        }
+    }
+
+    /// Compressed base64 string that can be shared or stored, led by `REPLAY_VERSION`
+    pub fn to_replay_string(&self) -> String {
+        let bytes = rkyv::to_bytes::<_, 1024>(self).unwrap();
+        let mut versioned = vec![REPLAY_VERSION];
+        versioned.extend(compress_prepend_size(&bytes));
+        base64::encode(versioned)
+    }
+
+    /// On failure returns a message fit to show the player
+    pub fn from_replay_string(s: &str) -> Result<Self, &'static str> {
+        let versioned = base64::decode(s.trim()).map_err(|_| "NOT A VALID REPLAY")?;
+        match versioned.first() {
+            Some(&REPLAY_VERSION) => (),
+            Some(version) if *version > REPLAY_VERSION => {
+                return Err("REPLAY IS FROM A NEWER VERSION");
+            }
+            // Replays from before the version byte start straight with the compressed size
+            _ if decompress_size_prepended(&versioned).is_ok() => {
+                return Err("REPLAY IS FROM AN OLDER VERSION");
+            }
+            _ => return Err("NOT A VALID REPLAY"),
+        }
+        let bytes = decompress_size_prepended(&versioned[1..]).map_err(|_| "NOT A VALID REPLAY")?;
+        let archived = rkyv::check_archived_root::<ActionRecording>(&bytes)
+            .map_err(|_| "NOT A VALID REPLAY")?;
+        archived
+            .deserialize(&mut rkyv::Infallible)
+            .map_err(|_| "NOT A VALID REPLAY")
+    }
+}
 
 #[derive(Resource, Default)]
 pub struct GameRecorder {
@@ -193,6 +444,26 @@ pub struct GameRecorder {
     pub play_head: usize,
 }
 
+impl GameRecorder {
+    /// Restart the game as a new run, recording from the beginning
+    pub fn start_recording(&mut self, action_queue: &mut ActionQueue, run: RunConfig) {
+        action_queue.push(Action::RestartGame);
+        self.actions = ActionRecording::new(run);
+        self.play = false;
+        self.disable_rec = false;
+        self.play_head = 0;
+    }
+
+    /// Restart the game and play back the given recording from the beginning
+    pub fn start_playback(&mut self, action_queue: &mut ActionQueue, actions: ActionRecording) {
+        action_queue.push(Action::RestartGame);
+        self.actions = actions;
+        self.play = true;
+        self.disable_rec = true;
+        self.play_head = 0;
+    }
+}
+
 #[derive(Resource, Deref, DerefMut, Default)]
 pub struct ActionQueue(pub Vec<Action>);
 
@@ -214,6 +485,19 @@ pub enum Action {
     CheatCredits,
     CheatHealth,
     CheatLevel,
+    GameSpeedMax,
+    RepairTurret(u8, u8),
+    BuyBaseShield,
+    EconomyPlace(u8, u8),
+    WallPlace(u8, u8),
+    /// Repeats the placement just before it over the rectangle from that cell to this one, so a
+    /// drag is recorded as two actions however many cells it covers
+    AreaTo(u8, u8),
+    Undo,
+    /// Picks up the turret for the `MoveTo` directly after it, like `AreaTo` a move takes two
+    /// actions to fit both cells
+    MoveFrom(u8, u8),
+    MoveTo(u8, u8),
 }
 
 impl Action {
@@ -235,6 +519,15 @@ impl Action {
             Action::CheatCredits                 => [12,  0,  0],
             Action::CheatHealth                  => [13,  0,  0],
             Action::CheatLevel                   => [14,  0,  0],
+            Action::GameSpeedMax                 => [15,  0,  0],
+            Action::RepairTurret(x, y) => [16, *x, *y],
+            Action::BuyBaseShield                => [17,  0,  0],
+            Action::EconomyPlace(x, y) => [18, *x, *y],
+            Action::WallPlace(x, y)    => [19, *x, *y],
+            Action::AreaTo(x, y)       => [20, *x, *y],
+            Action::Undo                         => [21,  0,  0],
+            Action::MoveFrom(x, y)     => [22, *x, *y],
+            Action::MoveTo(x, y)       => [23, *x, *y],
         }
     }
 
@@ -257,7 +550,87 @@ impl Action {
             12 => Action::CheatCredits,
             13 => Action::CheatHealth,
             14 => Action::CheatLevel,
+            15 => Action::GameSpeedMax,
+            16 => Action::RepairTurret(x, y),
+            17 => Action::BuyBaseShield,
+            18 => Action::EconomyPlace(x, y),
+            19 => Action::WallPlace(x, y),
+            20 => Action::AreaTo(x, y),
+            21 => Action::Undo,
+            22 => Action::MoveFrom(x, y),
+            23 => Action::MoveTo(x, y),
             _ => Action::Empty,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn action_bytes_round_trip() {
+        for code in 0..=23 {
+            let action = Action::from_bytes([code, 3, 7]);
+            assert_eq!(action.to_bytes()[0], code);
+            assert_eq!(Action::from_bytes(action.to_bytes()), action);
+        }
+        assert_eq!(Action::from_bytes([23, 3, 7]), Action::MoveTo(3, 7));
+        assert_eq!(Action::from_bytes([24, 3, 7]), Action::Empty);
+    }
+
+    #[test]
+    fn area_cells_start_at_the_anchor() {
+        assert_eq!(area_cells((2, 2), (2, 2)), vec![(2, 2)]);
+        assert_eq!(area_cells((3, 1), (1, 1)), vec![(3, 1), (2, 1), (1, 1)]);
+        assert_eq!(
+            area_cells((1, 2), (2, 1)),
+            vec![(1, 2), (2, 2), (1, 1), (2, 1)]
+        );
+    }
+
+    #[test]
+    fn replay_string_round_trip() {
+        let mut recording = ActionRecording::new(RunConfig::tutorial());
+        recording
+            .actions
+            .push((12, Action::BlasterPlace(6, 5).to_bytes()));
+        let replay = recording.to_replay_string();
+        assert_eq!(ActionRecording::from_replay_string(&replay), Ok(recording));
+        assert!(ActionRecording::from_replay_string("not a replay").is_err());
+    }
+
+    #[test]
+    fn same_replay_same_result() {
+        let mut recording = ActionRecording::new(RunConfig::random(
+            crate::run::Difficulty::Normal,
+            Default::default(),
+        ));
+        for (step, action) in [
+            (1, Action::BlasterPlace(6, 5)),
+            (2, Action::WallPlace(7, 7)),
+            (2, Action::AreaTo(7, 10)),
+            (400, Action::LaserPlace(10, 9)),
+        ] {
+            recording.actions.push((step, action.to_bytes()));
+        }
+        let first = crate::schedule::simulate_replay(recording.clone());
+        let second = crate::schedule::simulate_replay(recording);
+        assert_eq!(
+            (
+                first.step,
+                first.level,
+                first.kills,
+                first.credits,
+                first.health
+            ),
+            (
+                second.step,
+                second.level,
+                second.kills,
+                second.credits,
+                second.health
+            )
+        );
+    }
+}
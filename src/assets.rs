@@ -8,7 +8,8 @@ pub struct FontAssets {
     pub mono_medium: Handle<Font>,
 }
 
-#[derive(Resource, AssetCollection)]
+// Default gives placeholder handles, for running the simulation headless
+#[derive(Resource, AssetCollection, Default)]
 pub struct ModelAssets {
     // --- Units ---
     #[asset(path = "models/units/laser.glb#Scene0")]
@@ -24,6 +25,11 @@ pub struct ModelAssets {
     pub rolling_enemy_2: Handle<Scene>,
     #[asset(path = "models/units/flying_unit.glb#Scene0")]
     pub flying_enemy: Handle<Scene>,
+    // Own slot so a boss model can drop in, until then the roller is scaled up in `spawn_boss`
+    #[asset(path = "models/units/rolling_unit.glb#Scene0")]
+    pub boss_enemy: Handle<Scene>,
+    #[asset(path = "models/units/wall.glb#Scene0")]
+    pub wall: Handle<Scene>,
 
     #[asset(path = "models/units/base.glb#Scene0")]
     pub base: Handle<Scene>,
@@ -82,6 +88,52 @@ pub struct AudioAssets {
     pub music: Handle<AudioSource>,
 }
 
+/// Source files behind each collection, so the loading screen can show progress and name a file
+/// that failed. Keep in step with the `#[asset]` paths above
+pub const COLLECTION_PATHS: [(&str, &[&str]); 3] = [
+    ("FONTS", &["fonts/ShareTechMono-Regular.ttf"]),
+    (
+        "MODELS",
+        &[
+            "models/units/laser.glb",
+            "models/units/shockwave.glb",
+            "models/units/laser_turret_2.glb",
+            "models/units/rolling_unit.glb",
+            "models/units/rolling_unit_2.glb",
+            "models/units/flying_unit.glb",
+            // boss_enemy
+            "models/units/rolling_unit.glb",
+            "models/units/wall.glb",
+            "models/units/base.glb",
+            "models/units/base_destroyed.glb",
+            "models/projectiles/laser_blast.glb",
+            "models/misc/disc.glb",
+            "models/misc/board.glb",
+        ],
+    ),
+    (
+        "AUDIO",
+        &[
+            "audio/units/laser1.flac",
+            "audio/units/laser2.flac",
+            "audio/units/laser3.flac",
+            "audio/units/laser4.flac",
+            "audio/units/wave1.flac",
+            "audio/units/wave2.flac",
+            "audio/units/wave3.flac",
+            "audio/units/wave4.flac",
+            "audio/units/exp1.flac",
+            "audio/units/exp2.flac",
+            "audio/units/exp3.flac",
+            "audio/units/exp4.flac",
+            "audio/units/exp5.flac",
+            "audio/units/exp6.flac",
+            "audio/units/con_laser.flac",
+            "audio/music/music.ogg",
+        ],
+    ),
+];
+
 /*
 TODO game in 0.9 looks different than in 0.8
 There was an issue with a clipping plane in board.glb that has been fixed, but it still looks different.
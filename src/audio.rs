@@ -3,7 +3,7 @@ use std::time::Duration;
 use bevy_kira_audio::{AudioControl, AudioInstance, AudioPlugin, AudioSettings, AudioTween};
 use rand::seq::SliceRandom;
 
-use crate::{assets::AudioAssets, ui::Preferences, GameRng, GameState};
+use crate::{assets::AudioAssets, ui::Preferences, CosmeticRng, GameState};
 
 use bevy::prelude::*;
 use iyes_loopless::prelude::*;
@@ -25,13 +25,11 @@ impl Plugin for GameAudioPlugin {
                 command_capacity: 32,
             })
             .add_plugin(AudioPlugin)
+            .add_enter_system(GameState::MainMenu, menu_audio)
+            .add_enter_system(GameState::GameOver, menu_audio)
             .add_enter_system(GameState::RunLevel, setup_audio)
-            .add_system_set(
-                ConditionSet::new()
-                    .run_in_state(GameState::RunLevel)
-                    .with_system(run_audio)
-                    .into(),
-            );
+            .add_exit_system(GameState::RunLevel, stop_level_audio)
+            .add_system(run_audio.run_not_in_state(GameState::AssetLoading));
     }
 }
 
@@ -44,15 +42,72 @@ pub const EXPLOSION_SOUND: u8 = 1 << 5;
 
 const SFX_OFFSET: f64 = 0.25;
 const MUSIC_OFFSET: f64 = 0.15;
+/// Music is quieter behind menus and the game over screen
+const MENU_MUSIC: f64 = 0.5;
+const MUSIC_FADE: f32 = 2.0;
 
 #[derive(Resource, Default, Debug, Deref, DerefMut)]
 pub struct AudioEvents(pub u8);
 
+fn music_volume(pref: &Preferences, state: &GameState) -> f64 {
+    let level = pref.music * MUSIC_OFFSET;
+    match state {
+        GameState::RunLevel => level,
+        _ => level * MENU_MUSIC,
+    }
+}
+
+/// Fades the music to a new volume, starting it if it isn't playing yet
+fn fade_music(
+    music_h: &mut MusicAudioHandle,
+    audio_instances: &mut Assets<AudioInstance>,
+    audio: &bevy_kira_audio::Audio,
+    audio_assets: &AudioAssets,
+    volume: f64,
+) {
+    if let Some(music_h) = &music_h.0 {
+        if let Some(instance) = audio_instances.get_mut(music_h) {
+            instance.set_volume(
+                volume,
+                AudioTween::linear(Duration::from_secs_f32(MUSIC_FADE)),
+            );
+            return;
+        }
+    }
+    let inst = audio
+        .play(audio_assets.music.clone())
+        .with_volume(volume)
+        .fade_in(AudioTween::linear(Duration::from_secs_f32(10.0)))
+        .looped()
+        .handle();
+    music_h.0 = Some(inst);
+}
+
+fn menu_audio(
+    mut music_h: ResMut<MusicAudioHandle>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    audio_assets: Res<AudioAssets>,
+    audio: Res<bevy_kira_audio::Audio>,
+    pref: Res<Preferences>,
+    state: Res<CurrentState<GameState>>,
+) {
+    let volume = music_volume(&pref, &state.0);
+    fade_music(
+        &mut music_h,
+        &mut audio_instances,
+        &audio,
+        &audio_assets,
+        volume,
+    );
+}
+
 fn setup_audio(
     mut con_laser_h: ResMut<ConLaserAudioHandle>,
     mut music_h: ResMut<MusicAudioHandle>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
     audio_assets: Res<AudioAssets>,
     audio: Res<bevy_kira_audio::Audio>,
+    pref: Res<Preferences>,
 ) {
     let inst = audio
         .play(audio_assets.con_laser.clone())
@@ -61,13 +116,25 @@ fn setup_audio(
         .handle();
     con_laser_h.0 = Some(inst);
 
-    let inst = audio
-        .play(audio_assets.music.clone())
-        .with_volume(0.15)
-        .fade_in(AudioTween::linear(Duration::from_secs_f32(10.0)))
-        .looped()
-        .handle();
-    music_h.0 = Some(inst);
+    fade_music(
+        &mut music_h,
+        &mut audio_instances,
+        &audio,
+        &audio_assets,
+        music_volume(&pref, &GameState::RunLevel),
+    );
+}
+
+/// The continuous laser loop belongs to a run, a new one starts with the next
+fn stop_level_audio(
+    mut con_laser_h: ResMut<ConLaserAudioHandle>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+) {
+    if let Some(con_laser_h) = con_laser_h.0.take() {
+        if let Some(instance) = audio_instances.get_mut(&con_laser_h) {
+            instance.stop(AudioTween::default());
+        }
+    }
 }
 
 fn run_audio(
@@ -78,7 +145,8 @@ fn run_audio(
     mut audio_events_res: ResMut<AudioEvents>,
     music_h: Res<MusicAudioHandle>,
     pref: Res<Preferences>,
-    mut rng: ResMut<GameRng>,
+    mut rng: ResMut<CosmeticRng>,
+    state: Res<CurrentState<GameState>>,
 ) {
     let sfx_level = SFX_OFFSET * pref.sfx;
     let events = **audio_events_res;
@@ -150,7 +218,7 @@ fn run_audio(
         if let Some(music_h) = &music_h.0 {
             if let Some(instance) = audio_instances.get_mut(music_h) {
                 instance.set_volume(
-                    pref.music * MUSIC_OFFSET,
+                    music_volume(&pref, &state.0),
                     AudioTween::linear(Duration::from_secs_f32(0.1)),
                 );
             }
@@ -1,12 +1,29 @@
 use bevy::{math::*, prelude::*};
 use pathfinding::prelude::astar;
+use rand::Rng;
+use rand_pcg::Pcg32;
 
-use crate::turrets::Turret;
+use crate::{run::RunConfig, turrets::Turret};
 
 #[derive(Clone, Default)]
 pub struct Cell {
     pub filled: bool,
     pub turret: Option<(Turret, Entity)>,
+    pub wall: Option<Entity>,
+    /// Permanent blocker from the run's map modifiers, can't be sold
+    pub obstacle: bool,
+    /// Wrecked turret lying in the otherwise free cell, until it's repaired or built over
+    pub wreck: Option<(Turret, Entity)>,
+}
+
+/// Extra path cost of digging through a filled cell
+const TUNNEL_COST: u32 = 6;
+
+/// Per unit rules for moving across the board
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Traversal {
+    /// Filled cells the unit can still pass through
+    pub tunnels: u32,
 }
 
 #[derive(Resource)]
@@ -38,6 +55,92 @@ impl GameBoard {
         }
     }
 
+    /// Board for a run, with obstacles scattered from the seed for daily challenges
+    pub fn for_run(run: &RunConfig) -> GameBoard {
+        let mut b = GameBoard::default();
+        if run.daily.is_some() {
+            let mut rng = Pcg32::new(run.seed, 0xb0a2d);
+            let count = rng.gen_range(8..20);
+            for _ in 0..count {
+                let ls = ivec2(
+                    rng.gen_range(0..b.size[0] as i32),
+                    rng.gen_range(0..b.size[1] as i32),
+                );
+                // Keep clear of the spawn and the base
+                if (ls - b.start).abs().max_element() < 2 || (ls - b.dest).abs().max_element() < 2 {
+                    continue;
+                }
+                let idx = b.ls_to_idx(ls);
+                if b.board[idx].filled {
+                    continue;
+                }
+                b.board[idx].filled = true;
+                if b.path(b.start, b.dest).is_some() {
+                    b.board[idx].obstacle = true;
+                } else {
+                    b.board[idx].filled = false;
+                }
+            }
+        }
+        b
+    }
+
+    /// Whether a piece can go in this cell without cutting off the path to the base
+    pub fn can_fill(&mut self, idx: usize) -> bool {
+        if self.board[idx].filled || idx == self.ls_to_idx(self.start) {
+            return false;
+        }
+        self.board[idx].filled = true; //Just temp fill so we can check
+        let possible_path = self.path(self.start, self.dest);
+        self.board[idx].filled = false; //Undo temp fill
+        possible_path.is_some()
+    }
+
+    /// Moves the turret in `from` to `to`, both cells change together and only if the path to the
+    /// base stays open with the turret in its new cell
+    pub fn move_turret(&mut self, from: usize, to: usize) -> bool {
+        if from == to || self.board[from].turret.is_none() {
+            return false;
+        }
+        self.board[from].filled = false;
+        if !self.can_fill(to) {
+            self.board[from].filled = true;
+            return false;
+        }
+        self.board[to].turret = self.board[from].turret.take();
+        self.board[to].filled = true;
+        true
+    }
+
+    /// Frees the cell of a turret that was just wrecked, leaving the wreck behind in it
+    pub fn wreck(&mut self, entity: Entity) {
+        let cell = self
+            .board
+            .iter_mut()
+            .find(|cell| cell.turret.map(|(_, e)| e) == Some(entity));
+        if let Some(cell) = cell {
+            cell.wreck = cell.turret.take();
+            cell.filled = false;
+        }
+    }
+
+    /// Puts a wreck back in its cell as a turret, only if the path to the base stays open
+    pub fn restore_wreck(&mut self, idx: usize) -> bool {
+        if self.board[idx].wreck.is_none() || !self.can_fill(idx) {
+            return false;
+        }
+        self.board[idx].turret = self.board[idx].wreck.take();
+        self.board[idx].filled = true;
+        true
+    }
+
+    /// Removes the wreck in a cell that is being built over
+    pub fn clear_wreck(&mut self, com: &mut Commands, idx: usize) {
+        if let Some((_, entity)) = self.board[idx].wreck.take() {
+            com.entity(entity).despawn_recursive();
+        }
+    }
+
     pub fn reset_has_enemy(&mut self) {
         self.has_enemy = vec![false; self.size[0] * self.size[1]];
     }
@@ -68,19 +171,31 @@ impl GameBoard {
     }
 
     pub fn path(&self, start: IVec2, end: IVec2) -> Option<(Vec<IVec2>, u32)> {
+        self.path_for(start, end, Traversal::default())
+    }
+
+    /// Path for a unit with its own traversal rules. Search nodes carry the tunnels the unit
+    /// still has left at that point, so a tunnel is only spent once along a path.
+    pub fn path_for(
+        &self,
+        start: IVec2,
+        end: IVec2,
+        traversal: Traversal,
+    ) -> Option<(Vec<IVec2>, u32)> {
         astar(
-            &start,
-            |p| self.successors(*p),
-            |p| {
+            &(start, traversal.tunnels),
+            |(p, tunnels)| self.successors(*p, *tunnels),
+            |(p, _)| {
                 let a = (end - *p).abs();
                 (a.x + a.y) as u32
             },
-            |p| *p == end,
+            |(p, _)| *p == end,
         )
+        .map(|(path, cost)| (path.into_iter().map(|(p, _)| p).collect(), cost))
     }
 
     #[inline(always)]
-    pub fn successors(&self, ls: IVec2) -> Vec<(IVec2, u32)> {
+    pub fn successors(&self, ls: IVec2, tunnels: u32) -> Vec<((IVec2, u32), u32)> {
         let mut s = Vec::new();
         let mut diags = [ivec2(-1, -1), ivec2(1, -1), ivec2(1, 1), ivec2(-1, 1)];
         for offset in [
@@ -91,10 +206,10 @@ impl GameBoard {
             ivec2(0, 1),
         ] {
             let potential_pos = ls + offset;
-            if potential_pos.clamp(IVec2::ZERO, ivec2(self.size[0] as i32, self.size[1] as i32))
-                != potential_pos
-                || self.board[self.ls_to_idx(potential_pos)].filled
-            {
+            let in_bounds = potential_pos
+                .clamp(IVec2::ZERO, ivec2(self.size[0] as i32, self.size[1] as i32))
+                == potential_pos;
+            if !in_bounds || self.board[self.ls_to_idx(potential_pos)].filled {
                 // Directions that are blocked also block adjacent diagonal directions
                 for diag in &mut diags {
                     if diag.x == offset.x {
@@ -105,15 +220,19 @@ impl GameBoard {
                     }
                 }
 
+                // Tunnelers can dig straight through, but prefer going around
+                if in_bounds && tunnels > 0 {
+                    s.push(((potential_pos, tunnels - 1), TUNNEL_COST));
+                }
                 continue;
             } else {
-                s.push((potential_pos, 1));
+                s.push(((potential_pos, tunnels), 1));
             }
         }
         // Include diagonal directions that are not blocked
         for diag in &mut diags {
             if *diag != ivec2(0, 0) {
-                s.push((*diag, 1));
+                s.push(((*diag, tunnels), 1));
             }
         }
 
@@ -127,13 +246,17 @@ impl GameBoard {
     }
     pub fn destroy(&mut self, com: &mut Commands, idx: usize) -> Option<Turret> {
         let mut rturret = None;
-        if self.board[idx].filled {
+        if self.board[idx].filled && !self.board[idx].obstacle {
             if let Some(turret) = &self.board[idx].turret {
                 com.entity(turret.1).despawn_recursive();
                 rturret = Some(turret.0);
             }
+            if let Some(wall) = self.board[idx].wall {
+                com.entity(wall).despawn_recursive();
+            }
             self.board[idx].filled = false;
             self.board[idx].turret = None;
+            self.board[idx].wall = None;
         }
         rturret
     }
@@ -146,3 +269,50 @@ pub fn vec3_to_ivec2(p: Vec3) -> IVec2 {
 pub fn ivec2_to_vec3(p: IVec2) -> Vec3 {
     vec3(p.x as f32, 0.0, p.y as f32)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Board with a wall across row 10 that only leaves `gap` open
+    fn walled_board(gap: i32) -> GameBoard {
+        let mut b = GameBoard::default();
+        for x in (0..b.size[0] as i32).filter(|x| *x != gap) {
+            let idx = b.ls_to_idx(ivec2(x, 10));
+            b.board[idx].filled = true;
+        }
+        b
+    }
+
+    #[test]
+    fn can_fill_rejects_blocking_cells() {
+        let mut b = walled_board(5);
+        let gap = b.ls_to_idx(ivec2(5, 10));
+        let start = b.ls_to_idx(b.start);
+        let wall = b.ls_to_idx(ivec2(6, 10));
+        assert!(!b.can_fill(gap));
+        assert!(!b.can_fill(start));
+        assert!(!b.can_fill(wall));
+        assert!(b.can_fill(b.ls_to_idx(ivec2(12, 12))));
+        // Checking leaves the board as it was
+        assert!(!b.board[gap].filled);
+    }
+
+    #[test]
+    fn move_turret_keeps_the_path_open() {
+        let mut b = walled_board(5);
+        let from = b.ls_to_idx(ivec2(15, 15));
+        let gap = b.ls_to_idx(ivec2(5, 10));
+        b.board[from].filled = true;
+        b.board[from].turret = Some((Turret::Blaster, Entity::from_raw(1)));
+
+        assert!(!b.move_turret(from, gap));
+        assert!(b.board[from].filled && b.board[from].turret.is_some());
+        assert!(!b.board[gap].filled && b.board[gap].turret.is_none());
+
+        let to = b.ls_to_idx(ivec2(15, 16));
+        assert!(b.move_turret(from, to));
+        assert!(!b.board[from].filled && b.board[from].turret.is_none());
+        assert!(b.board[to].filled && b.board[to].turret.is_some());
+    }
+}
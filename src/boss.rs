@@ -0,0 +1,114 @@
+use bevy::{math::*, prelude::*};
+use bevy_scene_hook::{HookedSceneBundle, SceneHook};
+
+use crate::{
+    assets::ModelAssets,
+    basic_light,
+    board::GameBoard,
+    enemies::{AttackCooldown, Enemy, EnemyKind, EnemyPath, Health, LastSpawns},
+    player::{GameSettings, PlayerState},
+    status::StatusEffects,
+    ui::Preferences,
+};
+
+/// Health fraction below which the boss enrages
+const ENRAGE_HEALTH: f32 = 0.5;
+const ENRAGE_SPEED: f32 = 1.6;
+/// The boss is a scaled up roller, told apart by size and its magenta light
+const BOSS_SCALE: f32 = 1.8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BossPhase {
+    /// Comes alone, normal spawns wait until it's dealt with or enraged
+    Advance,
+    /// Hurt badly, speeds up and lets the regular waves back in
+    Enraged,
+}
+
+impl BossPhase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BossPhase::Advance => "ADVANCING",
+            BossPhase::Enraged => "ENRAGED",
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Boss {
+    pub max_health: f32,
+    pub phase: BossPhase,
+}
+
+pub(crate) fn spawn_boss(
+    mut com: Commands,
+    mut last_spawns: ResMut<LastSpawns>,
+    b: Res<GameBoard>,
+    model_assets: Res<ModelAssets>,
+    player: Res<PlayerState>,
+    pref: Res<Preferences>,
+    settings: Res<GameSettings>,
+) {
+    if !player.alive() {
+        return;
+    }
+    let next_level = match settings.boss_levels.get(last_spawns.bosses) {
+        Some(level) => *level as f32,
+        None => return,
+    };
+    if player.level < next_level || b.has_enemy[0] {
+        return;
+    }
+    last_spawns.bosses += 1;
+
+    let health = settings.boss_health * player.enemy_health_mult(&settings);
+    let mut ecmds = com.spawn_empty();
+    ecmds
+        .insert(EnemyPath::default())
+        .insert(Health(health))
+        .insert(Enemy {
+            speed: settings.boss_speed + player.enemy_speed_boost(&settings),
+        })
+        .insert(EnemyKind::Boss)
+        .insert(StatusEffects::default())
+        .insert(AttackCooldown::default())
+        .insert(Boss {
+            max_health: health,
+            phase: BossPhase::Advance,
+        });
+
+    basic_light(
+        &mut ecmds,
+        Color::rgb(1.0, 0.0, 0.6),
+        400.0,
+        4.0 * pref.light_r,
+        0.4,
+        vec3(0.0, 0.8, -0.4),
+    );
+
+    ecmds.insert(HookedSceneBundle {
+        scene: SceneBundle {
+            scene: model_assets.boss_enemy.clone(),
+            transform: Transform::from_translation(b.ls_to_ws_vec3(b.start))
+                .with_scale(Vec3::splat(BOSS_SCALE)),
+            ..default()
+        },
+        hook: SceneHook::new(move |_entity, _cmds| {}),
+    });
+}
+
+pub(crate) fn update_bosses(
+    mut bosses: Query<(&Health, &mut Boss, &mut Enemy)>,
+    mut last_spawns: ResMut<LastSpawns>,
+) {
+    last_spawns.paused_by_boss = false;
+    for (health, mut boss, mut enemy) in bosses.iter_mut() {
+        if boss.phase == BossPhase::Advance && health.0 < boss.max_health * ENRAGE_HEALTH {
+            boss.phase = BossPhase::Enraged;
+            enemy.speed *= ENRAGE_SPEED;
+        }
+        if boss.phase == BossPhase::Advance {
+            last_spawns.paused_by_boss = true;
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use std::f32::consts::TAU;
+
+use bevy::{
+    input::mouse::{MouseMotion, MouseWheel},
+    math::*,
+    prelude::*,
+    render::camera::{Projection, ScalingMode},
+};
+use bevy_egui::EguiContext;
+use bevy_mod_raycast::RaycastSource;
+use iyes_loopless::prelude::*;
+
+use crate::{board::GameBoard, player::MyRaycastSet, GameState};
+
+pub struct CameraPlugin;
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_enter_system(GameState::RunLevel, setup_camera.after("RESET RUN"))
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::RunLevel)
+                    .label("CAMERA INPUT")
+                    .with_system(camera_mouse)
+                    .with_system(camera_keyboard)
+                    .with_system(camera_touch)
+                    .into(),
+            )
+            .add_system(
+                update_camera
+                    .run_in_state(GameState::RunLevel)
+                    .after("CAMERA INPUT"),
+            );
+    }
+}
+
+const FOV: f32 = 16.0 * TAU / 360.0;
+/// Distance that frames the default 24x24 board, scaled for other board sizes
+const BOARD_DISTANCE: f32 = 82.0;
+const MIN_DISTANCE: f32 = 20.0;
+const MIN_PITCH: f32 = 15.0 * TAU / 360.0;
+const MAX_PITCH: f32 = TAU / 4.0;
+const ISO_YAW: f32 = TAU / 8.0;
+const ISO_PITCH: f32 = 36.5 * TAU / 360.0;
+/// Pixels from the window edge where the cursor starts panning
+const EDGE_MARGIN: f32 = 8.0;
+/// Pan speed in board widths per second at the default zoom
+const PAN_SPEED: f32 = 0.6;
+const ROTATE_SPEED: f32 = 1.5;
+const MOUSE_ROTATE: f32 = 0.005;
+const ZOOM_STEP: f32 = 0.1;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CameraView {
+    /// Perspective from an angle, free to orbit
+    Isometric,
+    /// Orthographic straight down
+    TopDown,
+}
+
+/// Orbit camera around a focus point on the board plane
+#[derive(Component)]
+pub struct CameraRig {
+    pub view: CameraView,
+    pub focus: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub distance: f32,
+    /// Board extents the focus stays inside, and the zoom that frames the whole board
+    min: Vec3,
+    max: Vec3,
+    board_distance: f32,
+}
+
+impl CameraRig {
+    pub fn new(b: &GameBoard) -> Self {
+        let min = b.ls_to_ws_vec3(IVec2::ZERO) - vec3(0.5, 0.0, 0.5);
+        let max = b.ls_to_ws_vec3(ivec2(b.size[0] as i32, b.size[1] as i32)) - vec3(0.5, 0.0, 0.5);
+        let board_distance = BOARD_DISTANCE * b.size[0].max(b.size[1]) as f32 / 24.0;
+        let mut rig = CameraRig {
+            view: CameraView::Isometric,
+            focus: Vec3::ZERO,
+            yaw: 0.0,
+            pitch: 0.0,
+            distance: 0.0,
+            min,
+            max,
+            board_distance,
+        };
+        rig.set_view(CameraView::Isometric);
+        rig
+    }
+
+    /// Jumps to a preset, framing the whole board
+    pub fn set_view(&mut self, view: CameraView) {
+        self.view = view;
+        self.focus = (self.min + self.max) * 0.5;
+        self.distance = self.board_distance;
+        match view {
+            CameraView::Isometric => {
+                // Slightly off center, the same framing the game always had
+                self.focus += vec3(4.9, 0.0, -1.1);
+                self.yaw = ISO_YAW;
+                self.pitch = ISO_PITCH;
+            }
+            CameraView::TopDown => {
+                self.yaw = 0.0;
+                self.pitch = MAX_PITCH;
+            }
+        }
+    }
+
+    /// Moves the focus by a screen aligned offset, scaled with zoom
+    pub fn pan(&mut self, right: f32, forward: f32) {
+        let zoom = self.distance / self.board_distance;
+        let size = (self.max - self.min).max_element();
+        let right_dir = vec3(self.yaw.cos(), 0.0, -self.yaw.sin());
+        let forward_dir = vec3(-self.yaw.sin(), 0.0, -self.yaw.cos());
+        self.focus += (right_dir * right + forward_dir * forward) * size * zoom;
+    }
+
+    pub fn rotate(&mut self, yaw: f32, pitch: f32) {
+        // Top down is a fixed view, rotating it would only spin the board
+        if self.view == CameraView::TopDown {
+            return;
+        }
+        self.yaw += yaw;
+        self.pitch += pitch;
+    }
+
+    /// Positive zooms in
+    pub fn zoom(&mut self, amount: f32) {
+        self.distance *= 1.0 - amount;
+    }
+
+    fn clamp(&mut self) {
+        self.focus = self.focus.clamp(self.min, self.max);
+        self.pitch = self.pitch.clamp(MIN_PITCH, MAX_PITCH);
+        self.distance = self.distance.clamp(MIN_DISTANCE, self.board_distance * 1.5);
+    }
+
+    fn transform(&self) -> Transform {
+        let rotation = Quat::from_euler(EulerRot::YXZ, self.yaw, -self.pitch, 0.0);
+        Transform::from_translation(self.focus + rotation * Vec3::Z * self.distance)
+            .with_rotation(rotation)
+    }
+
+    fn projection(&self) -> Projection {
+        match self.view {
+            CameraView::Isometric => Projection::Perspective(PerspectiveProjection {
+                fov: FOV,
+                ..default()
+            }),
+            // Shows the same height of board as perspective would at this distance
+            CameraView::TopDown => Projection::Orthographic(OrthographicProjection {
+                scaling_mode: ScalingMode::FixedVertical(2.0 * self.distance * (FOV * 0.5).tan()),
+                far: self.distance * 2.0,
+                ..default()
+            }),
+        }
+    }
+}
+
+fn setup_camera(mut com: Commands, b: Res<GameBoard>) {
+    let rig = CameraRig::new(&b);
+    com.spawn(Camera3dBundle {
+        transform: rig.transform(),
+        projection: rig.projection(),
+        ..default()
+    })
+    .insert(rig)
+    .insert(RaycastSource::<MyRaycastSet>::new());
+}
+
+fn camera_mouse(
+    mut rig: Query<&mut CameraRig>,
+    mut egui_context: ResMut<EguiContext>,
+    buttons: Res<Input<MouseButton>>,
+    mut wheel: EventReader<MouseWheel>,
+    mut motion: EventReader<MouseMotion>,
+    windows: Res<Windows>,
+    time: Res<Time>,
+) {
+    let mut rig = match rig.get_single_mut() {
+        Ok(rig) => rig,
+        Err(_) => return,
+    };
+    let over_ui = egui_context.ctx_mut().is_pointer_over_area();
+    let delta = motion.iter().map(|m| m.delta).sum::<Vec2>();
+    let scroll = wheel.iter().map(|w| w.y.signum()).sum::<f32>();
+    if over_ui {
+        return;
+    }
+
+    // The rig is only touched on input, so `update_camera` can skip the frames where it sits still
+    if scroll != 0.0 {
+        rig.zoom(scroll * ZOOM_STEP);
+    }
+    if delta != Vec2::ZERO {
+        if buttons.pressed(MouseButton::Right) {
+            rig.rotate(-delta.x * MOUSE_ROTATE, delta.y * MOUSE_ROTATE);
+        }
+        if buttons.pressed(MouseButton::Middle) {
+            let window = windows.get_primary().unwrap();
+            rig.pan(-delta.x / window.height(), delta.y / window.height());
+        }
+    }
+
+    // Edge pan, only while the window has the cursor
+    let window = windows.get_primary().unwrap();
+    if let Some(cursor) = window.cursor_position() {
+        let mut dir = Vec2::ZERO;
+        if cursor.x < EDGE_MARGIN {
+            dir.x -= 1.0;
+        } else if cursor.x > window.width() - EDGE_MARGIN {
+            dir.x += 1.0;
+        }
+        // Window coordinates start at the bottom
+        if cursor.y < EDGE_MARGIN {
+            dir.y -= 1.0;
+        } else if cursor.y > window.height() - EDGE_MARGIN {
+            dir.y += 1.0;
+        }
+        if dir != Vec2::ZERO {
+            let step = dir * PAN_SPEED * time.delta_seconds();
+            rig.pan(step.x, step.y);
+        }
+    }
+}
+
+fn camera_keyboard(
+    mut rig: Query<&mut CameraRig>,
+    mut egui_context: ResMut<EguiContext>,
+    keys: Res<Input<KeyCode>>,
+    time: Res<Time>,
+) {
+    let mut rig = match rig.get_single_mut() {
+        Ok(rig) => rig,
+        Err(_) => return,
+    };
+    if egui_context.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    // Ctrl is for shortcuts like undo
+    if keys.any_pressed([KeyCode::LControl, KeyCode::RControl]) {
+        return;
+    }
+    let dt = time.delta_seconds();
+    let axis = |neg: [KeyCode; 2], pos: [KeyCode; 2]| {
+        keys.any_pressed(pos) as i32 as f32 - keys.any_pressed(neg) as i32 as f32
+    };
+    let right = axis([KeyCode::A, KeyCode::Left], [KeyCode::D, KeyCode::Right]);
+    let forward = axis([KeyCode::S, KeyCode::Down], [KeyCode::W, KeyCode::Up]);
+    if right != 0.0 || forward != 0.0 {
+        rig.pan(right * PAN_SPEED * dt, forward * PAN_SPEED * dt);
+    }
+    let turn = axis([KeyCode::E], [KeyCode::Q]);
+    if turn != 0.0 {
+        rig.rotate(turn * ROTATE_SPEED * dt, 0.0);
+    }
+    let zoom = axis(
+        [KeyCode::Minus, KeyCode::NumpadSubtract],
+        [KeyCode::Equals, KeyCode::NumpadAdd],
+    );
+    if zoom != 0.0 {
+        rig.zoom(zoom * dt);
+    }
+
+    if keys.just_pressed(KeyCode::Key1) {
+        rig.set_view(CameraView::Isometric);
+    }
+    if keys.just_pressed(KeyCode::Key2) {
+        rig.set_view(CameraView::TopDown);
+    }
+}
+
+/// Two fingers pinch to zoom and drag to pan, one finger is left for building
+fn camera_touch(mut rig: Query<&mut CameraRig>, touches: Res<Touches>, windows: Res<Windows>) {
+    let mut rig = match rig.get_single_mut() {
+        Ok(rig) => rig,
+        Err(_) => return,
+    };
+    let fingers = touches.iter().collect::<Vec<_>>();
+    if fingers.len() != 2 {
+        return;
+    }
+    let (a, b) = (fingers[0], fingers[1]);
+    let spread = a.position().distance(b.position());
+    let last_spread = a.previous_position().distance(b.previous_position());
+    if last_spread > 0.0 && spread != last_spread {
+        rig.zoom(spread / last_spread - 1.0);
+    }
+    let height = windows.get_primary().unwrap().height();
+    // Touch coordinates start at the top
+    let moved = (a.delta() + b.delta()) * 0.5 / height;
+    if moved != Vec2::ZERO {
+        rig.pan(-moved.x, moved.y);
+    }
+}
+
+fn update_camera(
+    mut cameras: Query<(&mut CameraRig, &mut Transform, &mut Projection), Changed<CameraRig>>,
+) {
+    for (mut rig, mut trans, mut projection) in cameras.iter_mut() {
+        rig.clamp();
+        *trans = rig.transform();
+        *projection = rig.projection();
+    }
+}
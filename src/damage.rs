@@ -0,0 +1,39 @@
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DamageType {
+    Kinetic,
+    Energy,
+    Pulse,
+}
+
+impl DamageType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DamageType::Kinetic => "KINETIC",
+            DamageType::Energy => "ENERGY",
+            DamageType::Pulse => "PULSE",
+        }
+    }
+}
+
+/// Fraction of each damage type that is ignored. Kinetic resistance is the unit's armor.
+#[derive(Clone, Copy, Debug)]
+pub struct Resistances {
+    pub kinetic: f32,
+    pub energy: f32,
+    pub pulse: f32,
+}
+
+impl Resistances {
+    pub fn get(&self, damage_type: DamageType) -> f32 {
+        match damage_type {
+            DamageType::Kinetic => self.kinetic,
+            DamageType::Energy => self.energy,
+            DamageType::Pulse => self.pulse,
+        }
+    }
+
+    /// Multiplier on incoming damage of this type
+    pub fn effectiveness(&self, damage_type: DamageType) -> f32 {
+        1.0 - self.get(damage_type)
+    }
+}
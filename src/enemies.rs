@@ -1,16 +1,20 @@
-use bevy::{math::*, prelude::*};
+use bevy::{ecs::system::EntityCommands, math::*, prelude::*};
 
 use bevy_scene_hook::{HookedSceneBundle, SceneHook};
 use rand::Rng;
 
 use crate::{
+    abilities::{Ability, Tunneler, SPLIT_CHILDREN, SPLIT_CHILD_HEALTH},
     assets::ModelAssets,
     audio::{AudioEvents, EXPLOSION_SOUND},
     basic_light,
     board::GameBoard,
-    player::{PlayerState, GAMESETTINGS},
+    damage::Resistances,
+    player::{GameSettings, PlayerState},
     schedule::TIMESTEP,
-    turrets::DiscExplosion,
+    status::StatusEffects,
+    steering::{formation_offset, Steering, FORMATION_SIZE},
+    turrets::{Disabled, DiscExplosion, Turret, TurretHealth},
     ui::Preferences,
     GameRng,
 };
@@ -33,12 +37,99 @@ pub struct Health(pub f32);
 
 #[derive(Component)]
 pub struct Enemy {
-    speed: f32,
+    pub(crate) speed: f32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Layer {
+    Ground,
+    Air,
+}
+
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EnemyKind {
+    Rolling,
+    Rolling2,
+    Flying,
+    Boss,
+}
+
+impl EnemyKind {
+    pub const ALL: [EnemyKind; 4] = [
+        EnemyKind::Rolling,
+        EnemyKind::Rolling2,
+        EnemyKind::Flying,
+        EnemyKind::Boss,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EnemyKind::Rolling => "ROLLER",
+            EnemyKind::Rolling2 => "RUNNER",
+            EnemyKind::Flying => "FLYER",
+            EnemyKind::Boss => "BOSS",
+        }
+    }
+
+    pub fn layer(&self) -> Layer {
+        match self {
+            EnemyKind::Rolling | EnemyKind::Rolling2 | EnemyKind::Boss => Layer::Ground,
+            EnemyKind::Flying => Layer::Air,
+        }
+    }
+
+    pub fn resistances(&self, settings: &GameSettings) -> Resistances {
+        match self {
+            EnemyKind::Rolling => settings.rolling_enemy_resist,
+            EnemyKind::Rolling2 => settings.rolling_enemy_2_resist,
+            EnemyKind::Flying => settings.flying_enemy_resist,
+            EnemyKind::Boss => settings.boss_resist,
+        }
+    }
+
+    pub fn turret_attack(&self, settings: &GameSettings) -> Option<TurretAttack> {
+        match self {
+            EnemyKind::Rolling => Some(settings.rolling_enemy_attack),
+            EnemyKind::Boss => Some(settings.boss_attack),
+            EnemyKind::Rolling2 | EnemyKind::Flying => None,
+        }
+    }
+
+    /// Hit points the base loses when this reaches it
+    pub fn base_damage(&self, settings: &GameSettings) -> i32 {
+        match self {
+            EnemyKind::Rolling => settings.rolling_enemy_base_damage,
+            EnemyKind::Rolling2 => settings.rolling_enemy_2_base_damage,
+            EnemyKind::Flying => settings.flying_enemy_base_damage,
+            EnemyKind::Boss => settings.boss_base_damage,
+        }
+    }
+
+    pub fn kill_credits(&self, settings: &GameSettings) -> u64 {
+        match self {
+            EnemyKind::Rolling => settings.rolling_enemy_credits,
+            EnemyKind::Rolling2 => settings.rolling_enemy_2_credits,
+            EnemyKind::Flying => settings.flying_enemy_credits,
+            EnemyKind::Boss => settings.boss_credits,
+        }
+    }
 }
 
+/// Attack some units make on turrets they pass close to
+#[derive(Clone, Copy, Debug)]
+pub struct TurretAttack {
+    pub damage: f32,
+    pub range: f32,
+    /// Seconds between hits
+    pub interval: f32,
+}
+
+#[derive(Component, Default)]
+pub struct AttackCooldown(f32);
+
 #[derive(Component)]
 pub struct FlyingEnemy {
-    dest: Vec3,
+    pub(crate) dest: Vec3,
     new_rand_loc_timer: f32,
 }
 
@@ -47,6 +138,14 @@ pub struct LastSpawns {
     rolling_enemy: f32,
     rolling_enemy2: f32,
     flying_enemy: f32,
+    flying_spawned: u64,
+    flying_group: u64,
+    flying_group_size: u32,
+    flying_group_origin: Vec3,
+    /// Bosses spawned so far, as an index into `GameSettings::boss_levels`
+    pub(crate) bosses: usize,
+    /// Set while a boss holds back the regular spawns
+    pub(crate) paused_by_boss: bool,
 }
 
 pub(crate) fn spawn_rolling_enemy(
@@ -56,6 +155,8 @@ pub(crate) fn spawn_rolling_enemy(
     model_assets: Res<ModelAssets>,
     player: Res<PlayerState>,
     pref: Res<Preferences>,
+    mut rng: ResMut<GameRng>,
+    settings: Res<GameSettings>,
 ) {
     if !player.alive() {
         return;
@@ -64,25 +165,30 @@ pub(crate) fn spawn_rolling_enemy(
     if player.level < 20.0 {
         return;
     }
-    if b.has_enemy[0] {
+    if b.has_enemy[0] || last_spawns.paused_by_boss {
         return;
     }
     let since_startup = TIMESTEP * player.step as f32;
     if since_startup - last_spawns.rolling_enemy
-        > (GAMESETTINGS.rolling_enemy_spawn_speed - player.spawn_rate_cut())
-            .max(GAMESETTINGS.rolling_enemy_max_spawn_speed)
+        > (settings.rolling_enemy_spawn_speed - player.spawn_rate_cut(&settings))
+            .max(settings.rolling_enemy_max_spawn_speed)
     {
         last_spawns.rolling_enemy = since_startup;
         let mut ecmds = com.spawn_empty();
 
+        let health = settings.rolling_enemy_health * player.enemy_health_mult(&settings);
         ecmds
             .insert(EnemyPath::default())
-            .insert(Health(
-                GAMESETTINGS.rolling_enemy_health * player.enemy_health_mult(),
-            ))
+            .insert(Health(health))
             .insert(Enemy {
-                speed: GAMESETTINGS.rolling_enemy_speed + player.enemy_speed_boost(),
-            });
+                speed: settings.rolling_enemy_speed + player.enemy_speed_boost(&settings),
+            })
+            .insert(EnemyKind::Rolling)
+            .insert(StatusEffects::default())
+            .insert(AttackCooldown::default());
+        if let Some(ability) = Ability::roll(&settings, player.level, &mut rng) {
+            ability.insert(&mut ecmds, health);
+        }
 
         basic_light(
             &mut ecmds,
@@ -111,6 +217,8 @@ pub(crate) fn spawn_rolling_enemy2(
     model_assets: Res<ModelAssets>,
     player: Res<PlayerState>,
     pref: Res<Preferences>,
+    mut rng: ResMut<GameRng>,
+    settings: Res<GameSettings>,
 ) {
     if !player.alive() {
         return;
@@ -118,46 +226,68 @@ pub(crate) fn spawn_rolling_enemy2(
     if player.level < 1.0 {
         return;
     }
-    if b.has_enemy[0] {
+    if b.has_enemy[0] || last_spawns.paused_by_boss {
         return;
     }
     let since_startup = TIMESTEP * player.step as f32;
     if since_startup - last_spawns.rolling_enemy2
-        > (GAMESETTINGS.rolling_enemy_2_spawn_speed - player.spawn_rate_cut())
-            .max(GAMESETTINGS.rolling_enemy_2_max_spawn_speed)
+        > (settings.rolling_enemy_2_spawn_speed - player.spawn_rate_cut(&settings))
+            .max(settings.rolling_enemy_2_max_spawn_speed)
     {
         last_spawns.rolling_enemy2 = since_startup;
-        let mut ecmds = com.spawn_empty();
-
-        ecmds
-            .insert(EnemyPath::default())
-            .insert(Health(
-                GAMESETTINGS.rolling_enemy_2_health * player.enemy_health_mult(),
-            ))
-            .insert(Enemy {
-                speed: GAMESETTINGS.rolling_enemy_2_speed + player.enemy_speed_boost(),
-            });
-
-        basic_light(
-            &mut ecmds,
-            Color::rgb(1.0, 0.1, 0.3),
-            30.0,
-            1.5 * pref.light_r,
-            0.5,
-            vec3(0.0, 0.4, -0.5),
+        let health = settings.rolling_enemy_2_health * player.enemy_health_mult(&settings);
+        let mut ecmds = spawn_runner(
+            &mut com,
+            &model_assets,
+            &pref,
+            b.ls_to_ws_vec3(b.start),
+            health,
+            settings.rolling_enemy_2_speed + player.enemy_speed_boost(&settings),
         );
-
-        ecmds.insert(HookedSceneBundle {
-            scene: SceneBundle {
-                scene: model_assets.rolling_enemy_2.clone(),
-                transform: Transform::from_translation(b.ls_to_ws_vec3(b.start)),
-                ..default()
-            },
-            hook: SceneHook::new(move |_entity, _cmds| {}),
-        });
+        if let Some(ability) = Ability::roll(&settings, player.level, &mut rng) {
+            ability.insert(&mut ecmds, health);
+        }
     }
 }
 
+/// Runners come from the spawn timer and from splitters breaking apart
+fn spawn_runner<'w, 's, 'a>(
+    com: &'a mut Commands<'w, 's>,
+    model_assets: &ModelAssets,
+    pref: &Preferences,
+    pos: Vec3,
+    health: f32,
+    speed: f32,
+) -> EntityCommands<'w, 's, 'a> {
+    let mut ecmds = com.spawn_empty();
+
+    ecmds
+        .insert(EnemyPath::default())
+        .insert(Health(health))
+        .insert(Enemy { speed })
+        .insert(EnemyKind::Rolling2)
+        .insert(StatusEffects::default());
+
+    basic_light(
+        &mut ecmds,
+        Color::rgb(1.0, 0.1, 0.3),
+        30.0,
+        1.5 * pref.light_r,
+        0.5,
+        vec3(0.0, 0.4, -0.5),
+    );
+
+    ecmds.insert(HookedSceneBundle {
+        scene: SceneBundle {
+            scene: model_assets.rolling_enemy_2.clone(),
+            transform: Transform::from_translation(pos),
+            ..default()
+        },
+        hook: SceneHook::new(move |_entity, _cmds| {}),
+    });
+    ecmds
+}
+
 pub(crate) fn spawn_flying_enemy(
     mut com: Commands,
     mut last_spawns: ResMut<LastSpawns>,
@@ -166,6 +296,7 @@ pub(crate) fn spawn_flying_enemy(
     player: Res<PlayerState>,
     pref: Res<Preferences>,
     mut rng: ResMut<GameRng>,
+    settings: Res<GameSettings>,
 ) {
     if !player.alive() {
         return;
@@ -173,25 +304,27 @@ pub(crate) fn spawn_flying_enemy(
     if player.level < 10.0 {
         return;
     }
-    if b.has_enemy[0] {
+    if b.has_enemy[0] || last_spawns.paused_by_boss {
         return;
     }
     let since_startup = TIMESTEP * player.step as f32;
     if since_startup - last_spawns.flying_enemy
-        > (GAMESETTINGS.flying_enemy_spawn_speed
-            - player.spawn_rate_cut() * ((player.level - 20.0) * 0.25).clamp(1.0, 50.0))
-        .max(GAMESETTINGS.flying_enemy_max_spawn_speed)
+        > (settings.flying_enemy_spawn_speed
+            - player.spawn_rate_cut(&settings) * ((player.level - 20.0) * 0.25).clamp(1.0, 50.0))
+        .max(settings.flying_enemy_max_spawn_speed)
     {
         last_spawns.flying_enemy = since_startup;
         let mut ecmds = com.spawn_empty();
 
         ecmds
             .insert(Health(
-                GAMESETTINGS.flying_enemy_health * player.enemy_health_mult(),
+                settings.flying_enemy_health * player.enemy_health_mult(&settings),
             ))
             .insert(Enemy {
-                speed: GAMESETTINGS.flying_enemy_speed + player.enemy_speed_boost(),
+                speed: settings.flying_enemy_speed + player.enemy_speed_boost(&settings),
             })
+            .insert(EnemyKind::Flying)
+            .insert(StatusEffects::default())
             .insert(FlyingEnemy {
                 dest: b.ls_to_ws_vec3(b.dest),
                 new_rand_loc_timer: 0.0,
@@ -206,19 +339,35 @@ pub(crate) fn spawn_flying_enemy(
             vec3(0.0, 0.3, -0.2),
         );
 
-        // Random pos off screen
-        let rnd_offset = vec3(
-            rng.gen_range(-15.0..-5.0) as f32,
-            0.0,
-            rng.gen_range(-15.0..-5.0) as f32,
+        // Flyers arrive in formations, each group starts from its own random pos off screen
+        if last_spawns.flying_group_size == 0 || last_spawns.flying_group_size >= FORMATION_SIZE {
+            last_spawns.flying_group += 1;
+            last_spawns.flying_group_size = 0;
+            last_spawns.flying_group_origin = vec3(
+                rng.gen_range(-15.0..-5.0) as f32,
+                0.0,
+                rng.gen_range(-15.0..-5.0) as f32,
+            );
+        }
+        let slot = last_spawns.flying_group_size;
+        last_spawns.flying_group_size += 1;
+        last_spawns.flying_spawned += 1;
+        let steering = Steering::new(
+            last_spawns.flying_spawned,
+            last_spawns.flying_group,
+            slot,
+            &mut rng,
         );
 
+        let start = b.ls_to_ws_vec3(b.start) + last_spawns.flying_group_origin;
+        let forward = (b.ls_to_ws_vec3(b.dest) - start).normalize_or_zero();
+        let pos = start + formation_offset(slot, forward) + Vec3::Y * steering.cruise_altitude;
+        ecmds.insert(steering);
+
         ecmds.insert(HookedSceneBundle {
             scene: SceneBundle {
                 scene: model_assets.flying_enemy.clone(),
-                transform: Transform::from_translation(
-                    b.ls_to_ws_vec3(b.start) + Vec3::Y * 2.0 + rnd_offset,
-                ),
+                transform: Transform::from_translation(pos),
                 ..default()
             },
             hook: SceneHook::new(move |_entity, _cmds| {}),
@@ -228,19 +377,36 @@ pub(crate) fn spawn_flying_enemy(
 
 pub(crate) fn destroy_enemies(
     mut com: Commands,
-    enemies: Query<(Entity, &Health), With<Enemy>>,
+    enemies: Query<(Entity, &Health, &Transform, &EnemyKind, Option<&Ability>), With<Enemy>>,
     mut player: ResMut<PlayerState>,
     mut audio_events: ResMut<AudioEvents>,
+    model_assets: Res<ModelAssets>,
+    pref: Res<Preferences>,
+    settings: Res<GameSettings>,
 ) {
     if !player.alive() {
         return;
     }
-    for (entity, health) in enemies.iter() {
+    for (entity, health, trans, kind, ability) in enemies.iter() {
         if health.0 < 0.0 {
             com.entity(entity).despawn_recursive();
-            player.credits += GAMESETTINGS.credits_for_kill;
+            player.credits += kind.kill_credits(&settings);
             player.kills += 1;
             **audio_events |= EXPLOSION_SOUND;
+            if ability == Some(&Ability::Splitter) {
+                for _ in 0..SPLIT_CHILDREN {
+                    spawn_runner(
+                        &mut com,
+                        &model_assets,
+                        &pref,
+                        trans.translation,
+                        settings.rolling_enemy_2_health
+                            * player.enemy_health_mult(&settings)
+                            * SPLIT_CHILD_HEALTH,
+                        settings.rolling_enemy_2_speed + player.enemy_speed_boost(&settings),
+                    );
+                }
+            }
         }
     }
 }
@@ -261,14 +427,15 @@ pub struct PathInd;
 
 pub(crate) fn update_enemy_paths(
     b: Res<GameBoard>,
-    mut enemies: Query<(&Transform, &mut EnemyPath)>,
+    mut enemies: Query<(&Transform, &mut EnemyPath, Option<&Tunneler>)>,
     player: Res<PlayerState>,
 ) {
     if !player.alive() {
         return;
     }
-    for (trans, mut enemy_path) in enemies.iter_mut() {
-        enemy_path.path = b.path(b.ws_vec3_to_ls(trans.translation), b.dest);
+    for (trans, mut enemy_path, tunneler) in enemies.iter_mut() {
+        let traversal = tunneler.map(|t| t.traversal()).unwrap_or_default();
+        enemy_path.path = b.path_for(b.ws_vec3_to_ls(trans.translation), b.dest, traversal);
     }
 }
 
@@ -334,16 +501,17 @@ pub(crate) fn debug_show_enemy_path(
 
 pub(crate) fn move_enemy_along_path(
     b: Res<GameBoard>,
-    mut enemies: Query<(&mut Transform, &mut EnemyPath, &Enemy)>,
+    mut enemies: Query<(&mut Transform, &mut EnemyPath, &Enemy, &StatusEffects)>,
 ) {
-    for (mut enemy_trans, enemy_path, enemy) in enemies.iter_mut() {
+    for (mut enemy_trans, enemy_path, enemy, effects) in enemies.iter_mut() {
         if let Some(path) = &enemy_path.path {
             if path.0.len() > 1 {
                 let p = enemy_trans.translation;
                 let a = b.ls_to_ws_vec3(path.0[1]);
                 let next_pos = a;
                 if !b.has_enemy[b.ls_to_idx(b.ws_vec3_to_ls(next_pos))] {
-                    enemy_trans.translation += (next_pos - p).normalize() * TIMESTEP * enemy.speed;
+                    enemy_trans.translation +=
+                        (next_pos - p).normalize() * TIMESTEP * enemy.speed * effects.speed_mult();
                 }
                 enemy_trans.look_at(next_pos, Vec3::Y);
             }
@@ -351,17 +519,70 @@ pub(crate) fn move_enemy_along_path(
     }
 }
 
+pub(crate) fn attack_turrets(
+    mut com: Commands,
+    mut enemies: Query<(&Transform, &EnemyKind, &StatusEffects, &mut AttackCooldown)>,
+    mut turrets: Query<(Entity, &Transform, &mut TurretHealth), (With<Turret>, Without<Disabled>)>,
+    model_assets: Res<ModelAssets>,
+    player: Res<PlayerState>,
+    settings: Res<GameSettings>,
+) {
+    if !player.alive() {
+        return;
+    }
+    for (enemy_trans, kind, effects, mut cooldown) in enemies.iter_mut() {
+        let attack = if let Some(attack) = kind.turret_attack(&settings) {
+            attack
+        } else {
+            continue;
+        };
+        // Stunned units can't attack, and their cooldown doesn't run either
+        if effects.stun.active() {
+            continue;
+        }
+        cooldown.0 -= TIMESTEP;
+        if cooldown.0 > 0.0 {
+            continue;
+        }
+
+        let mut closest = None;
+        let mut closest_dist = attack.range;
+        for (entity, turret_trans, _health) in turrets.iter() {
+            let dist = turret_trans.translation.distance(enemy_trans.translation);
+            if dist < closest_dist {
+                closest = Some(entity);
+                closest_dist = dist;
+            }
+        }
+        if let Some(Ok((_, turret_trans, mut health))) = closest.map(|e| turrets.get_mut(e)) {
+            health.current -= attack.damage;
+            cooldown.0 = attack.interval;
+            com.spawn(SceneBundle {
+                scene: model_assets.disc.clone(),
+                transform: Transform::from_translation(turret_trans.translation + Vec3::Y * 0.5),
+                ..Default::default()
+            })
+            .insert(DiscExplosion {
+                speed: 6.0,
+                size: 1.5,
+                progress: 0.0,
+            });
+        }
+    }
+}
+
 pub(crate) fn check_enemy_at_dest(
     mut com: Commands,
     b: Res<GameBoard>,
-    enemies: Query<(Entity, &Transform), With<Enemy>>,
+    enemies: Query<(Entity, &Transform, &EnemyKind), With<Enemy>>,
     mut player: ResMut<PlayerState>,
     model_assets: Res<ModelAssets>,
     mut audio_events: ResMut<AudioEvents>,
+    settings: Res<GameSettings>,
 ) {
-    for (enemy_entity, enemy_trans) in enemies.iter() {
+    for (enemy_entity, enemy_trans, kind) in enemies.iter() {
         if enemy_trans.translation.distance(b.ls_to_ws_vec3(b.dest)) < 1.0 {
-            player.health -= 0.1;
+            player.leak(*kind, &settings);
             com.entity(enemy_entity).despawn_recursive();
             let mut ecmds = com.spawn(SceneBundle {
                 scene: model_assets.disc.clone(),
@@ -386,25 +607,18 @@ pub(crate) fn check_enemy_at_dest(
     }
 }
 
-pub(crate) fn move_flying_enemy(mut enemies: Query<(&mut Transform, &mut FlyingEnemy, &Enemy)>) {
-    for (mut enemy_trans, fly_enemy, enemy) in enemies.iter_mut() {
-        enemy_trans.look_at(fly_enemy.dest, Vec3::Y);
-        let dir = (fly_enemy.dest - enemy_trans.translation).normalize();
-        enemy_trans.translation += dir * enemy.speed * TIMESTEP;
-    }
-}
-
 pub(crate) fn check_flying_enemy_at_dest(
     mut com: Commands,
     b: Res<GameBoard>,
-    enemies: Query<(Entity, &Transform), With<FlyingEnemy>>,
+    enemies: Query<(Entity, &Transform, &EnemyKind), With<FlyingEnemy>>,
     mut player: ResMut<PlayerState>,
     model_assets: Res<ModelAssets>,
     mut audio_events: ResMut<AudioEvents>,
+    settings: Res<GameSettings>,
 ) {
-    for (enemy_entity, enemy_trans) in enemies.iter() {
+    for (enemy_entity, enemy_trans, kind) in enemies.iter() {
         if enemy_trans.translation.distance(b.ls_to_ws_vec3(b.dest)) < 0.5 {
-            player.health -= 0.05;
+            player.leak(*kind, &settings);
             com.entity(enemy_entity).despawn_recursive();
             let mut ecmds = com.spawn(SceneBundle {
                 scene: model_assets.disc.clone(),
@@ -0,0 +1,198 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+
+use crate::{
+    action::{ActionRecording, GameRecorder},
+    player::PlayerState,
+    schedule::HeadlessReplay,
+    storage, GameState,
+};
+
+const HIGH_SCORES_KEY: &str = "decaphase_high_scores";
+const MAX_HIGH_SCORES: usize = 10;
+/// Real time per frame spent re-simulating replays
+const VERIFY_BUDGET: Duration = Duration::from_millis(4);
+
+pub struct HighScoresPlugin;
+impl Plugin for HighScoresPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.insert_resource(HighScores::default())
+            .insert_resource(ScoreVerifier::load())
+            .add_system(verify_scores)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::RunLevel)
+                    .with_system(submit_high_score)
+                    .into(),
+            );
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct HighScore {
+    pub level: u32,
+    pub kills: u64,
+    /// Difficulty and modifiers the run was played with
+    pub rules: String,
+    pub replay: String,
+}
+
+/// Only entries whose replay has been re-simulated and reproduced the score make it in here
+#[derive(Resource, Deref, DerefMut, Clone, Default, Debug)]
+pub struct HighScores(pub Vec<HighScore>);
+
+impl HighScores {
+    /// Only the replays are stored; level and kills are re-derived from them on load
+    fn save(&self) {
+        let replays: Vec<&str> = self.iter().map(|entry| entry.replay.as_str()).collect();
+        storage::save(HIGH_SCORES_KEY, &replays.join("\n"));
+    }
+
+    fn qualifies(&self, level: u32, kills: u64) -> bool {
+        match self.last() {
+            Some(worst) if self.len() >= MAX_HIGH_SCORES => {
+                (level, kills) > (worst.level, worst.kills)
+            }
+            _ => true,
+        }
+    }
+
+    fn insert(&mut self, entry: HighScore) -> bool {
+        if !self.qualifies(entry.level, entry.kills)
+            || self.iter().any(|other| other.replay == entry.replay)
+        {
+            return false;
+        }
+        self.push(entry);
+        self.sort_by(|a, b| (b.level, b.kills).cmp(&(a.level, a.kills)));
+        self.truncate(MAX_HIGH_SCORES);
+        true
+    }
+}
+
+struct PendingScore {
+    actions: ActionRecording,
+    replay: String,
+    /// Level and kills seen when the run ended, None for entries loaded from storage
+    claimed: Option<(u32, u64)>,
+}
+
+/// Replays waiting to be re-simulated before they can go on the high score table. Stepped a slice
+/// per frame so a long run doesn't stall the frame it ends on.
+#[derive(Resource, Default)]
+pub struct ScoreVerifier {
+    queue: VecDeque<PendingScore>,
+    current: Option<(HeadlessReplay, PendingScore)>,
+}
+
+impl ScoreVerifier {
+    fn load() -> Self {
+        let mut verifier = Self::default();
+        let stored = storage::load(HIGH_SCORES_KEY).unwrap_or_default();
+        for replay in stored.lines().filter(|line| !line.trim().is_empty()) {
+            match ActionRecording::from_replay_string(replay) {
+                Ok(actions) => verifier.queue.push_back(PendingScore {
+                    actions,
+                    replay: replay.trim().to_string(),
+                    claimed: None,
+                }),
+                Err(e) => warn!("Dropped stored high score: {}", e),
+            }
+        }
+        verifier
+    }
+
+    fn submit(&mut self, actions: &ActionRecording, level: u32, kills: u64) {
+        self.queue.push_back(PendingScore {
+            actions: actions.clone(),
+            replay: actions.to_replay_string(),
+            claimed: Some((level, kills)),
+        });
+    }
+
+    /// Number of replays still to be checked
+    pub fn pending(&self) -> usize {
+        self.queue.len() + self.current.is_some() as usize
+    }
+
+    pub fn is_checking(&self, replay: &str) -> bool {
+        self.current
+            .iter()
+            .map(|(_, pending)| pending)
+            .chain(self.queue.iter())
+            .any(|pending| pending.replay == replay)
+    }
+
+    /// Saving while stored entries are still unchecked would drop them from storage
+    fn loading_stored(&self) -> bool {
+        self.current
+            .iter()
+            .map(|(_, pending)| pending)
+            .chain(self.queue.iter())
+            .any(|pending| pending.claimed.is_none())
+    }
+}
+
+fn verify_scores(mut verifier: ResMut<ScoreVerifier>, mut high_scores: ResMut<HighScores>) {
+    if verifier.current.is_none() {
+        verifier.current = match verifier.queue.pop_front() {
+            Some(pending) => Some((HeadlessReplay::new(pending.actions.clone()), pending)),
+            None => return,
+        };
+    }
+    let (mut replay, pending) = match verifier.current.take() {
+        Some(current) => current,
+        None => return,
+    };
+    if !replay.run_for(VERIFY_BUDGET) {
+        verifier.current = Some((replay, pending));
+        return;
+    }
+
+    let result = replay.player();
+    let (level, kills) = (result.level as u32, result.kills);
+    match pending.claimed {
+        Some(claimed) if claimed != (level, kills) => warn!(
+            "Rejected high score: claimed level {} kills {}, replay gives level {} kills {}",
+            claimed.0, claimed.1, level, kills
+        ),
+        _ => {
+            high_scores.insert(HighScore {
+                level,
+                kills,
+                rules: pending.actions.run.rules_label(),
+                replay: pending.replay,
+            });
+        }
+    }
+    if !verifier.loading_stored() {
+        high_scores.save();
+    }
+}
+
+fn submit_high_score(
+    player: Res<PlayerState>,
+    game_recorder: Res<GameRecorder>,
+    high_scores: Res<HighScores>,
+    mut verifier: ResMut<ScoreVerifier>,
+    mut submitted: Local<bool>,
+) {
+    if player.alive() {
+        *submitted = false;
+        return;
+    }
+    if *submitted {
+        return;
+    }
+    *submitted = true;
+    let (level, kills) = (player.level as u32, player.kills);
+    // Only runs the player actually played, not watched replays
+    if !game_recorder.play
+        && !game_recorder.actions.run.is_tutorial()
+        && high_scores.qualifies(level, kills)
+    {
+        verifier.submit(&game_recorder.actions, level, kills);
+    }
+}
@@ -0,0 +1,63 @@
+use bevy::{asset::LoadState, prelude::*};
+use bevy_egui::{egui, EguiContext};
+use iyes_loopless::prelude::*;
+
+use crate::{assets::COLLECTION_PATHS, menu::menu_screen, GameState};
+
+pub struct LoadingPlugin;
+impl Plugin for LoadingPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.insert_resource(LoadingFailures::default())
+            .add_startup_system(spawn_loading_camera)
+            .add_system(ui_loading.run_in_state(GameState::AssetLoading))
+            .add_system(ui_loading_failed.run_in_state(GameState::LoadingFailed));
+    }
+}
+
+/// Asset paths that couldn't be loaded
+#[derive(Resource, Deref, DerefMut, Default)]
+pub struct LoadingFailures(pub Vec<&'static str>);
+
+/// Something has to clear the screen before the menus are up
+fn spawn_loading_camera(mut com: Commands) {
+    com.spawn(Camera2dBundle::default());
+}
+
+fn ui_loading(
+    mut com: Commands,
+    mut egui_context: ResMut<EguiContext>,
+    asset_server: Res<AssetServer>,
+    mut failures: ResMut<LoadingFailures>,
+) {
+    menu_screen(egui_context.ctx_mut(), false, "DECAPHASE", |ui| {
+        ui.label("LOADING");
+        ui.label("");
+        for (name, paths) in COLLECTION_PATHS {
+            let mut loaded = 0;
+            for path in paths {
+                match asset_server.get_load_state(*path) {
+                    LoadState::Loaded => loaded += 1,
+                    LoadState::Failed => failures.push(*path),
+                    _ => (),
+                }
+            }
+            ui.label(&format!("{} {}/{}", name, loaded, paths.len()));
+            ui.add(egui::ProgressBar::new(loaded as f32 / paths.len() as f32));
+        }
+    });
+    // The collections would wait on a failed file forever
+    if !failures.is_empty() {
+        com.insert_resource(NextState(GameState::LoadingFailed));
+    }
+}
+
+fn ui_loading_failed(mut egui_context: ResMut<EguiContext>, failures: Res<LoadingFailures>) {
+    menu_screen(egui_context.ctx_mut(), false, "DECAPHASE", |ui| {
+        ui.label("FAILED TO LOAD");
+        for path in failures.iter() {
+            ui.label(*path);
+        }
+        ui.label("");
+        ui.label("CHECK THE CONNECTION AND RELOAD");
+    });
+}
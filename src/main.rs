@@ -2,38 +2,63 @@
 
 use std::f32::consts::TAU;
 
+use abilities::AbilitiesPlugin;
+use action::{ActionRecording, GameRecorder};
 use assets::{AudioAssets, FontAssets, ModelAssets};
 use audio::GameAudioPlugin;
 use bevy::{
     ecs::{schedule::ShouldRun, system::EntityCommands},
     math::*,
     prelude::*,
-    render::camera::Projection,
     window::{PresentMode, WindowMode, WindowResizeConstraints},
 };
 use bevy_asset_loader::prelude::{LoadingState, LoadingStateAppExt};
+use camera::CameraPlugin;
 
-use bevy_mod_raycast::{RaycastMesh, RaycastSource};
+use bevy_mod_raycast::RaycastMesh;
 
 use bevy_scene_hook::{HookPlugin, HookedSceneBundle, SceneHook};
 use board::GameBoard;
 
 use enemies::{EnemiesPlugin, Enemy, LastSpawns};
+use highscores::HighScoresPlugin;
 use iyes_loopless::prelude::*;
-use player::{MyRaycastSet, PlayerState};
+use loading::LoadingPlugin;
+use menu::MenuPlugin;
+use player::{GameSettings, Inspected, MyRaycastSet, PlayerState};
 
 use rand_pcg::Pcg32;
+use run::{Difficulty, Modifiers, RunConfig};
+use schedule::SimSpeed;
+use status::StatusPlugin;
+use tactical::{PlannedActions, TacticalPlugin};
 use turrets::{Disabled, Projectile, Turret};
+use tutorial::TutorialPlugin;
 use ui::GameUI;
+use walls::Wall;
+pub mod abilities;
 pub mod action;
 pub mod assets;
 pub mod audio;
 pub mod board;
+pub mod boss;
+pub mod camera;
+pub mod damage;
 pub mod enemies;
+pub mod highscores;
+pub mod loading;
+pub mod menu;
 pub mod player;
+pub mod run;
 pub mod schedule;
+pub mod status;
+pub mod steering;
+pub mod storage;
+pub mod tactical;
 pub mod turrets;
+pub mod tutorial;
 pub mod ui;
+pub mod walls;
 
 fn main() {
     let mut app = App::new();
@@ -41,7 +66,7 @@ fn main() {
         .add_loopless_state(PausedState::Unpaused)
         .add_loading_state(
             LoadingState::new(GameState::AssetLoading)
-                .continue_to_state(GameState::RunLevel)
+                .continue_to_state(GameState::MainMenu)
                 .with_collection::<FontAssets>()
                 .with_collection::<ModelAssets>()
                 .with_collection::<AudioAssets>(),
@@ -79,14 +104,30 @@ fn main() {
                     ..Default::default()
                 }),
         )
-        .insert_resource(GameBoard::default())
-        .insert_resource(RestartGame::default())
-        .insert_resource(GameRng::default())
         .add_plugin(HookPlugin);
 
+    let run = RunConfig::random(Difficulty::Normal, Modifiers::default());
+    app.insert_resource(GameBoard::for_run(&run))
+        .insert_resource(GameSettings::for_run(&run))
+        .insert_resource(RestartGame::default())
+        .insert_resource(GameRng::new(run.seed))
+        .insert_resource(CosmeticRng::default())
+        .insert_resource(GameRecorder {
+            actions: ActionRecording::new(run),
+            ..default()
+        });
+
     app.add_plugin(GameUI)
+        .add_plugin(AbilitiesPlugin)
+        .add_plugin(CameraPlugin)
         .add_plugin(EnemiesPlugin)
-        .add_plugin(GameAudioPlugin);
+        .add_plugin(GameAudioPlugin)
+        .add_plugin(HighScoresPlugin)
+        .add_plugin(LoadingPlugin)
+        .add_plugin(MenuPlugin)
+        .add_plugin(StatusPlugin)
+        .add_plugin(TacticalPlugin)
+        .add_plugin(TutorialPlugin);
     schedule::setup_schedule(&mut app);
 
     #[cfg(target_arch = "wasm32")]
@@ -94,23 +135,39 @@ fn main() {
         app.add_plugin(bevy_web_resizer::Plugin);
     }
 
-    app.add_enter_system(GameState::RunLevel, setup_level)
+    app.add_enter_system(GameState::RunLevel, despawn_level)
+        .add_enter_system(GameState::RunLevel, reset_run.label("RESET RUN"))
+        .add_enter_system(GameState::RunLevel, setup_level.after("RESET RUN"))
         .add_system_set(
             ConditionSet::new()
                 .run_in_state(GameState::RunLevel)
                 .with_system(destroy_base_disable_turrets)
+                .with_system(update_obstacle_gfx)
                 .into(),
         );
 
     app.run();
 }
 
+/// Randomness for the simulation only. Seeded per run so replays reproduce, so anything that
+/// doesn't affect gameplay (audio, vfx) must use `CosmeticRng` instead.
 #[derive(Resource, Deref, DerefMut)]
 pub struct GameRng(pub Pcg32);
 
-impl Default for GameRng {
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        GameRng(Pcg32::new(seed, 0xa02bdbf7bb3c0a7))
+    }
+}
+
+/// Randomness for presentation. Never read by simulation systems, so how often it's drawn from
+/// can't change gameplay.
+#[derive(Resource, Deref, DerefMut)]
+pub struct CosmeticRng(pub Pcg32);
+
+impl Default for CosmeticRng {
     fn default() -> Self {
-        GameRng(Pcg32::new(0xcafef00dd15ea5e5, 0xa02bdbf7bb3c0a7))
+        CosmeticRng(Pcg32::new(run::now_unix_millis(), 0xcafef00dd15ea5e5))
     }
 }
 
@@ -158,19 +215,6 @@ fn setup_level(
         ),
         ..default()
     });
-    let side = 3.0;
-    // camera
-    com.spawn(Camera3dBundle {
-        transform: (Transform::from_translation(vec3(48.0 + side, 48.0, 48.0 - side)))
-            .looking_at(vec3(side, -2.0, -side), Vec3::Y),
-        projection: Projection::Perspective(PerspectiveProjection {
-            fov: 16f32.to_radians(),
-            ..default()
-        }),
-        ..default()
-    })
-    .insert(RaycastSource::<MyRaycastSet>::new());
-
     // Main Base
     spawn_main_base(&mut com, &model_assets, &b);
 }
@@ -211,7 +255,7 @@ fn destroy_base_disable_turrets(
     mut turrets: Query<Entity, With<Turret>>,
 ) {
     if let Some((main_base_entity, main_base_trans)) = main_base.iter().next() {
-        if player.health < 0.0 {
+        if !player.alive() {
             com.entity(main_base_entity).despawn_recursive();
             com.spawn(HookedSceneBundle {
                 scene: SceneBundle {
@@ -229,21 +273,120 @@ fn destroy_base_disable_turrets(
     }
 }
 
+#[derive(Component)]
+struct Obstacle;
+
+/// Respawns obstacle blocks whenever the board's obstacle layout changes
+fn update_obstacle_gfx(
+    mut com: Commands,
+    b: Res<GameBoard>,
+    obstacles: Query<Entity, With<Obstacle>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut last_layout: Local<Vec<usize>>,
+) {
+    let layout = (0..b.board.len())
+        .filter(|idx| b.board[*idx].obstacle)
+        .collect::<Vec<_>>();
+    // Blocks also go missing when the level is torn down between runs
+    if layout == *last_layout && obstacles.iter().count() == layout.len() {
+        return;
+    }
+    for e in obstacles.iter() {
+        com.entity(e).despawn_recursive();
+    }
+    let mesh = meshes.add(Mesh::from(shape::Box::new(0.9, 0.6, 0.9)));
+    let material = materials.add(StandardMaterial {
+        base_color: Color::rgb(0.1, 0.1, 0.1),
+        perceptual_roughness: 0.6,
+        ..default()
+    });
+    for idx in &layout {
+        com.spawn(PbrBundle {
+            mesh: mesh.clone(),
+            material: material.clone(),
+            transform: Transform::from_translation(
+                b.ls_to_ws_vec3(b.idx_to_ls(*idx)) + vec3(0.0, 0.3, 0.0),
+            ),
+            ..default()
+        })
+        .insert(Obstacle);
+    }
+    *last_layout = layout;
+}
+
 #[derive(Resource, Deref, DerefMut, Default)]
 pub struct RestartGame(bool);
 
+/// Clears out whatever the last screen left behind, the board of a finished run or the menu
+pub(crate) fn despawn_level(mut com: Commands, roots: Query<Entity, Without<Parent>>) {
+    for e in roots.iter() {
+        com.entity(e).despawn_recursive();
+    }
+}
+
+/// Fresh state for the run in `GameRecorder`, so nothing from the previous run is seen by the
+/// systems that spawn the level
+fn reset_run(
+    mut com: Commands,
+    mut player: ResMut<PlayerState>,
+    mut b: ResMut<GameBoard>,
+    mut rng: ResMut<GameRng>,
+    game_recorder: Res<GameRecorder>,
+    mut last_spawns: ResMut<LastSpawns>,
+    mut settings: ResMut<GameSettings>,
+    mut planned: ResMut<PlannedActions>,
+    mut inspected: ResMut<Inspected>,
+    mut sim_speed: ResMut<SimSpeed>,
+) {
+    reset_run_resources(
+        &game_recorder.actions.run,
+        &mut player,
+        &mut b,
+        &mut rng,
+        &mut settings,
+        &mut last_spawns,
+    );
+    planned.clear();
+    **inspected = None;
+    // A fast-forwarded replay shouldn't leave the next run at max speed
+    *sim_speed = SimSpeed::default();
+    com.insert_resource(NextState(PausedState::Unpaused));
+}
+
+fn reset_run_resources(
+    run: &RunConfig,
+    player: &mut PlayerState,
+    b: &mut GameBoard,
+    rng: &mut GameRng,
+    settings: &mut GameSettings,
+    last_spawns: &mut LastSpawns,
+) {
+    // Everything random in the run derives from its seed, so replays start identically
+    *b = GameBoard::for_run(run);
+    *rng = GameRng::new(run.seed);
+
+    *settings = GameSettings::for_run(run);
+    *player = PlayerState::new(settings);
+
+    *last_spawns = LastSpawns::default();
+}
+
 fn restart_game(
     mut com: Commands,
     mut restart_game: ResMut<RestartGame>,
     mut player: ResMut<PlayerState>,
     mut b: ResMut<GameBoard>,
+    mut rng: ResMut<GameRng>,
+    game_recorder: Res<GameRecorder>,
     model_assets: Res<ModelAssets>,
     old_base: Query<Entity, With<MainBaseDestroyed>>,
     new_base: Query<Entity, With<MainBase>>,
     enemies: Query<Entity, With<Enemy>>,
-    towers: Query<Entity, With<Turret>>,
+    towers: Query<Entity, Or<(With<Turret>, With<Wall>)>>,
     projectiles: Query<Entity, With<Projectile>>,
     mut last_spawns: ResMut<LastSpawns>,
+    mut settings: ResMut<GameSettings>,
 ) {
     if **restart_game {
         **restart_game = false;
@@ -262,15 +405,15 @@ fn restart_game(
         for e in projectiles.iter() {
             com.entity(e).despawn_recursive();
         }
-        *b = GameBoard::default();
-
-        let old_time_multiplier = player.time_multiplier;
-        *player = PlayerState::default();
-        player.time_multiplier = old_time_multiplier;
-
+        reset_run_resources(
+            &game_recorder.actions.run,
+            &mut player,
+            &mut b,
+            &mut rng,
+            &mut settings,
+            &mut last_spawns,
+        );
         spawn_main_base(&mut com, &model_assets, &b);
-
-        *last_spawns = LastSpawns::default();
     }
 }
 
@@ -300,7 +443,16 @@ pub fn basic_light(
 #[derive(Clone, Eq, PartialEq, Debug, Hash)]
 pub enum GameState {
     AssetLoading,
+    /// A file needed by the asset collections couldn't be loaded
+    LoadingFailed,
+    MainMenu,
+    LevelSelect,
+    Settings,
+    ReplayBrowser,
+    /// The simulation only runs here
     RunLevel,
+    /// The finished board stays on screen under the results
+    GameOver,
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, Hash)]
@@ -0,0 +1,313 @@
+#[cfg(not(target_arch = "wasm32"))]
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use iyes_loopless::prelude::*;
+
+use crate::{
+    action::{ActionQueue, ActionRecording, GameRecorder},
+    audio::AudioEvents,
+    despawn_level,
+    highscores::{HighScores, ScoreVerifier},
+    player::PlayerState,
+    run::{Difficulty, Modifiers, RunConfig},
+    tutorial::Tutorial,
+    ui::{panel_style, preference_controls, select_button, Preferences},
+    GameState,
+};
+
+pub struct MenuPlugin;
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.insert_resource(ReplayString::default())
+            .add_enter_system(GameState::MainMenu, despawn_level)
+            .add_enter_system(GameState::MainMenu, spawn_menu_camera)
+            .add_enter_system(GameState::GameOver, save_replay_string)
+            .add_system(ui_main_menu.run_in_state(GameState::MainMenu))
+            .add_system(ui_level_select.run_in_state(GameState::LevelSelect))
+            .add_system(ui_settings.run_in_state(GameState::Settings))
+            .add_system(ui_replay_browser.run_in_state(GameState::ReplayBrowser))
+            .add_system(check_game_over.run_in_state(GameState::RunLevel))
+            .add_system(ui_game_over.run_in_state(GameState::GameOver));
+    }
+}
+
+/// Real seconds the wrecked base stays in play before the results come up
+const GAME_OVER_DELAY: f32 = 3.0;
+const MENU_WIDTH: f32 = 320.0;
+
+/// Replay of the run that just ended, ready to copy
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct ReplayString(pub String);
+
+/// Nothing else is drawn behind the menus, but something has to clear the screen
+fn spawn_menu_camera(mut com: Commands) {
+    com.spawn(Camera2dBundle::default());
+}
+
+/// Centered column of menu items under a title
+pub(crate) fn menu_screen(
+    ctx: &egui::Context,
+    finger_sized: bool,
+    title: &str,
+    add_contents: impl FnOnce(&mut egui::Ui),
+) {
+    egui::CentralPanel::default()
+        .frame(egui::Frame::none())
+        .show(ctx, |ui| {
+            panel_style(ui.style_mut(), finger_sized);
+            ui.vertical_centered(|ui| {
+                ui.add_space(ui.available_height() * 0.1);
+                ui.heading(title);
+                ui.add_space(16.0);
+                ui.allocate_ui(egui::vec2(MENU_WIDTH, ui.available_height()), |ui| {
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        ui.vertical_centered_justified(add_contents);
+                    });
+                });
+            });
+        });
+}
+
+fn ui_main_menu(
+    mut com: Commands,
+    mut egui_context: ResMut<EguiContext>,
+    windows: Res<Windows>,
+    pref: Res<Preferences>,
+    high_scores: Res<HighScores>,
+    mut tutorial: ResMut<Tutorial>,
+    mut game_recorder: ResMut<GameRecorder>,
+    mut action_queue: ResMut<ActionQueue>,
+    #[cfg(not(target_arch = "wasm32"))] mut exit: EventWriter<AppExit>,
+) {
+    let finger_sized = pref.finger_sized(windows.get_primary().unwrap());
+    menu_screen(egui_context.ctx_mut(), finger_sized, "DECAPHASE", |ui| {
+        if let Some(best) = high_scores.first() {
+            ui.label(&format!(
+                "BEST LV {} K {} {}",
+                best.level, best.kills, best.rules
+            ));
+        }
+        if ui.button("PLAY").clicked() {
+            com.insert_resource(NextState(GameState::LevelSelect));
+        }
+        if ui.button("TUTORIAL").clicked() {
+            tutorial.start();
+            game_recorder.start_recording(&mut action_queue, RunConfig::tutorial());
+            com.insert_resource(NextState(GameState::RunLevel));
+        }
+        if ui.button("REPLAYS").clicked() {
+            com.insert_resource(NextState(GameState::ReplayBrowser));
+        }
+        if ui.button("SETTINGS").clicked() {
+            com.insert_resource(NextState(GameState::Settings));
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if ui.button("QUIT").clicked() {
+                exit.send(AppExit);
+            }
+        }
+    });
+}
+
+fn ui_level_select(
+    mut com: Commands,
+    mut egui_context: ResMut<EguiContext>,
+    windows: Res<Windows>,
+    pref: Res<Preferences>,
+    mut game_recorder: ResMut<GameRecorder>,
+    mut action_queue: ResMut<ActionQueue>,
+    mut next_rules: Local<(Difficulty, Modifiers)>,
+) {
+    let finger_sized = pref.finger_sized(windows.get_primary().unwrap());
+    menu_screen(egui_context.ctx_mut(), finger_sized, "NEW RUN", |ui| {
+        ui.label("DIFFICULTY");
+        for difficulty in Difficulty::ALL {
+            if select_button(ui, difficulty.name(), next_rules.0 == difficulty) {
+                next_rules.0 = difficulty;
+            }
+        }
+        ui.label("");
+        ui.label("MODIFIERS");
+        let modifiers = &mut next_rules.1;
+        ui.checkbox(&mut modifiers.double_flyers, "2X FLYERS");
+        ui.checkbox(&mut modifiers.no_selling, "NO SELLING");
+        ui.checkbox(&mut modifiers.half_credits, "HALF CREDITS");
+        ui.checkbox(&mut modifiers.fast_enemies, "FAST ENEMIES");
+        ui.label("");
+        if ui.button("START").clicked() {
+            let (difficulty, modifiers) = *next_rules;
+            game_recorder
+                .start_recording(&mut action_queue, RunConfig::random(difficulty, modifiers));
+            com.insert_resource(NextState(GameState::RunLevel));
+        }
+        let daily = RunConfig::daily();
+        if ui.button(&daily.label()).clicked() {
+            game_recorder.start_recording(&mut action_queue, daily);
+            com.insert_resource(NextState(GameState::RunLevel));
+        }
+        ui.label("");
+        if ui.button("BACK").clicked() {
+            com.insert_resource(NextState(GameState::MainMenu));
+        }
+    });
+}
+
+fn ui_settings(
+    mut com: Commands,
+    mut egui_context: ResMut<EguiContext>,
+    windows: Res<Windows>,
+    mut pref: ResMut<Preferences>,
+    mut audio_events: ResMut<AudioEvents>,
+) {
+    let finger_sized = pref.finger_sized(windows.get_primary().unwrap());
+    menu_screen(egui_context.ctx_mut(), finger_sized, "SETTINGS", |ui| {
+        preference_controls(ui, &mut pref, &mut audio_events);
+        ui.label("");
+        if ui.button("BACK").clicked() {
+            com.insert_resource(NextState(GameState::MainMenu));
+        }
+    });
+}
+
+fn ui_replay_browser(
+    mut com: Commands,
+    mut egui_context: ResMut<EguiContext>,
+    windows: Res<Windows>,
+    pref: Res<Preferences>,
+    high_scores: Res<HighScores>,
+    verifier: Res<ScoreVerifier>,
+    mut game_recorder: ResMut<GameRecorder>,
+    mut action_queue: ResMut<ActionQueue>,
+    mut rec_string: Local<String>,
+) {
+    let finger_sized = pref.finger_sized(windows.get_primary().unwrap());
+    let mut watch = None;
+    menu_screen(egui_context.ctx_mut(), finger_sized, "REPLAYS", |ui| {
+        ui.label("HIGH SCORES");
+        if high_scores.is_empty() && verifier.pending() == 0 {
+            ui.label("NONE YET");
+        }
+        for (i, entry) in high_scores.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(&format!(
+                    "{}. LV {} K {} {}",
+                    i + 1,
+                    entry.level,
+                    entry.kills,
+                    entry.rules
+                ));
+                if ui.button("WATCH").clicked() {
+                    watch = ActionRecording::from_replay_string(&entry.replay).ok();
+                }
+            });
+        }
+        if verifier.pending() > 0 {
+            ui.label(&format!("CHECKING {} REPLAYS", verifier.pending()));
+        }
+        ui.label("");
+        ui.label("PASTE A REPLAY STRING");
+        ui.text_edit_singleline(&mut *rec_string);
+        if !rec_string.is_empty() {
+            match ActionRecording::from_replay_string(&rec_string) {
+                Ok(actions) => {
+                    ui.label(&actions.run.label());
+                    ui.label(&actions.run.rules_label());
+                    if ui.button("WATCH").clicked() {
+                        watch = Some(actions);
+                    }
+                }
+                Err(e) => {
+                    ui.label(e);
+                }
+            }
+        }
+        ui.label("");
+        if ui.button("BACK").clicked() {
+            com.insert_resource(NextState(GameState::MainMenu));
+        }
+    });
+    if let Some(actions) = watch {
+        game_recorder.start_playback(&mut action_queue, actions);
+        com.insert_resource(NextState(GameState::RunLevel));
+    }
+}
+
+fn check_game_over(
+    mut com: Commands,
+    player: Res<PlayerState>,
+    time: Res<Time>,
+    mut dead_for: Local<f32>,
+) {
+    if player.alive() {
+        *dead_for = 0.0;
+        return;
+    }
+    *dead_for += time.delta_seconds();
+    if *dead_for >= GAME_OVER_DELAY {
+        *dead_for = 0.0;
+        com.insert_resource(NextState(GameState::GameOver));
+    }
+}
+
+fn save_replay_string(game_recorder: Res<GameRecorder>, mut replay: ResMut<ReplayString>) {
+    **replay = game_recorder.actions.to_replay_string();
+}
+
+fn ui_game_over(
+    mut com: Commands,
+    mut egui_context: ResMut<EguiContext>,
+    windows: Res<Windows>,
+    pref: Res<Preferences>,
+    player: Res<PlayerState>,
+    high_scores: Res<HighScores>,
+    verifier: Res<ScoreVerifier>,
+    mut game_recorder: ResMut<GameRecorder>,
+    mut action_queue: ResMut<ActionQueue>,
+    mut replay: ResMut<ReplayString>,
+) {
+    let finger_sized = pref.finger_sized(windows.get_primary().unwrap());
+    egui::Window::new("GAME OVER")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .show(egui_context.ctx_mut(), |ui| {
+            panel_style(ui.style_mut(), finger_sized);
+            ui.vertical_centered_justified(|ui| {
+                let run = game_recorder.actions.run;
+                ui.label(&run.label());
+                ui.label(&run.rules_label());
+                ui.label(&format!(
+                    "LEVEL {}   KILLS {}",
+                    player.level as u32, player.kills
+                ));
+                let recorded = !game_recorder.play;
+                if recorded && high_scores.iter().any(|entry| entry.replay == **replay) {
+                    ui.label("NEW HIGH SCORE");
+                } else if recorded && verifier.is_checking(&replay) {
+                    ui.label("CHECKING SCORE");
+                }
+                ui.label("");
+                ui.label("REPLAY STRING");
+                ui.text_edit_singleline(&mut **replay);
+                ui.label("");
+                if ui.button("RETRY").clicked() {
+                    let next = match run.daily {
+                        Some(_) => RunConfig::daily(),
+                        None => RunConfig::random(run.difficulty, run.modifiers),
+                    };
+                    game_recorder.start_recording(&mut action_queue, next);
+                    com.insert_resource(NextState(GameState::RunLevel));
+                }
+                if ui.button("WATCH REPLAY").clicked() {
+                    let actions = game_recorder.actions.clone();
+                    game_recorder.start_playback(&mut action_queue, actions);
+                    com.insert_resource(NextState(GameState::RunLevel));
+                }
+                if ui.button("MAIN MENU").clicked() {
+                    com.insert_resource(NextState(GameState::MainMenu));
+                }
+            });
+        });
+}
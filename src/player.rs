@@ -1,30 +1,115 @@
 use bevy::{math::*, prelude::*};
+use bevy_egui::EguiContext;
 use bevy_mod_raycast::{Intersection, RaycastMethod, RaycastSource};
 
 use crate::{
-    action::{Action, ActionQueue},
+    action::{Action, ActionQueue, GameRecorder},
     board::GameBoard,
+    damage::Resistances,
+    enemies::{EnemyKind, TurretAttack},
+    run::{Difficulty, RunConfig},
     schedule::TIMESTEP,
     turrets::Turret,
+    ui::Preferences,
 };
 
+/// Tuning for a run. `GAMESETTINGS` is the baseline, the resource used by the simulation is built
+/// from it at game start with `GameSettings::for_run`.
+#[derive(Resource, Clone)]
 pub struct GameSettings {
     pub rolling_enemy_health: f32,
     pub rolling_enemy_speed: f32,
     pub rolling_enemy_spawn_speed: f32,
     pub rolling_enemy_max_spawn_speed: f32,
+    pub rolling_enemy_resist: Resistances,
+    pub rolling_enemy_attack: TurretAttack,
+    pub rolling_enemy_base_damage: i32,
+    pub rolling_enemy_credits: u64,
     //
     pub rolling_enemy_2_health: f32,
     pub rolling_enemy_2_speed: f32,
     pub rolling_enemy_2_spawn_speed: f32,
     pub rolling_enemy_2_max_spawn_speed: f32,
+    pub rolling_enemy_2_resist: Resistances,
+    pub rolling_enemy_2_base_damage: i32,
+    pub rolling_enemy_2_credits: u64,
     //
     pub flying_enemy_health: f32,
     pub flying_enemy_speed: f32,
     pub flying_enemy_spawn_speed: f32,
     pub flying_enemy_max_spawn_speed: f32,
+    pub flying_enemy_resist: Resistances,
+    pub flying_enemy_evasion: bool,
+    pub flying_enemy_base_damage: i32,
+    pub flying_enemy_credits: u64,
     //
-    pub credits_for_kill: u64,
+    /// Chance for a ground spawn to roll one of the abilities unlocked at the current level
+    pub enemy_ability_chance: f32,
+    //
+    pub boss_levels: &'static [u32],
+    pub boss_health: f32,
+    pub boss_speed: f32,
+    pub boss_resist: Resistances,
+    pub boss_credits: u64,
+    pub boss_base_damage: i32,
+    pub boss_attack: TurretAttack,
+    //
+    pub starting_credits: u64,
+    pub allow_selling: bool,
+    pub wall_cost: u64,
+    /// Fraction of the wall cost returned when selling one
+    pub wall_refund: f32,
+    /// Seconds after placing during which a piece can be undone for a full refund, it can also
+    /// always be undone within the level it was placed in
+    pub undo_grace: f32,
+    /// Fraction of a turret's cost charged to move it
+    pub move_fee: f32,
+    //
+    // Economy, paid out at each level boundary
+    /// Fraction of banked credits paid as interest, up to `interest_cap`
+    pub interest_rate: f32,
+    pub interest_cap: u64,
+    /// Paid if nothing reached the base during the level
+    pub no_leak_bonus: u64,
+    pub economy_turret_income: u64,
+    /// Seconds between economy turret payouts
+    pub economy_turret_interval: f32,
+    //
+    // Level curves
+    pub health_curve_exponent: f32,
+    pub speed_curve_scale: f32,
+    pub spawn_cut_scale: f32,
+    //
+    pub base: BaseSettings,
+    pub base_shield_cost: u64,
+    pub base_shield_amount: i32,
+    pub base_shield_max: i32,
+}
+
+/// Base durability for a difficulty
+#[derive(Clone, Copy, Debug)]
+pub struct BaseSettings {
+    pub hit_points: i32,
+    /// Hit points restored at each level boundary, never above the starting hit points
+    pub regen_per_level: i32,
+}
+
+/// A piece the player built, kept so it can be undone
+#[derive(Clone, Copy, Debug)]
+pub struct Placement {
+    pub step: u64,
+    pub level: f32,
+    pub idx: usize,
+    pub entity: Entity,
+    pub cost: u64,
+}
+
+/// An enemy that reached the base
+#[derive(Clone, Copy, Debug)]
+pub struct Leak {
+    pub step: u64,
+    pub kind: EnemyKind,
+    pub damage: i32,
 }
 
 #[derive(Resource)]
@@ -32,14 +117,26 @@ pub struct PlayerState {
     pub credits: u64,
     pub turret_to_place: Option<Turret>,
     pub kills: u64,
-    pub health: f32,
+    /// Base hit points
+    pub health: i32,
+    pub max_health: i32,
+    pub base_regen: i32,
+    /// Bought with credits, soaks leak damage before the base takes any
+    pub shield: i32,
+    pub leaks: Vec<Leak>,
+    /// Most recent last, `Action::Undo` takes them back in reverse order
+    pub placements: Vec<Placement>,
+    /// Something reached the base since the last level boundary
+    pub leaked_this_level: bool,
     pub sell_mode: bool,
+    pub repair_mode: bool,
+    pub wall_mode: bool,
+    pub move_mode: bool,
     pub blaster_upgrade: f32,
     pub laser_upgrade: f32,
     pub wave_upgrade: f32,
     pub level_time: f32,
     pub level: f32,
-    pub time_multiplier: f64,
     pub step: u64,
 }
 
@@ -48,34 +145,175 @@ pub const GAMESETTINGS: GameSettings = GameSettings {
     rolling_enemy_speed: 0.6,
     rolling_enemy_spawn_speed: 3.0,
     rolling_enemy_max_spawn_speed: 1.2,
+    // Heavy armor, shrugs off half of laser damage
+    rolling_enemy_resist: Resistances {
+        kinetic: 0.25,
+        energy: 0.5,
+        pulse: 0.0,
+    },
+    // Rams turrets it rolls past
+    rolling_enemy_attack: TurretAttack {
+        damage: 0.1,
+        range: 1.5,
+        interval: 1.5,
+    },
+    rolling_enemy_base_damage: 10,
+    rolling_enemy_credits: 35,
     //
     rolling_enemy_2_health: 0.28,
     rolling_enemy_2_speed: 1.2,
     rolling_enemy_2_spawn_speed: 3.5,
     rolling_enemy_2_max_spawn_speed: 1.1,
+    rolling_enemy_2_resist: Resistances {
+        kinetic: 0.0,
+        energy: 0.0,
+        pulse: 0.25,
+    },
+    rolling_enemy_2_base_damage: 10,
+    rolling_enemy_2_credits: 25,
     //
     flying_enemy_health: 0.08,
     flying_enemy_speed: 2.0,
     flying_enemy_spawn_speed: 3.0,
     flying_enemy_max_spawn_speed: 0.05,
+    // Waves only target the ground, so flyers never take pulse damage
+    flying_enemy_resist: Resistances {
+        kinetic: 0.0,
+        energy: 0.0,
+        pulse: 0.0,
+    },
+    // Weave side to side while turrets are aiming at them
+    flying_enemy_evasion: true,
+    flying_enemy_base_damage: 5,
+    flying_enemy_credits: 15,
+    //
+    enemy_ability_chance: 0.2,
+    //
+    boss_levels: &[
+        10, 20, 30, 40, 50, 60, 70, 80, 90, 100, 120, 140, 160, 180, 200,
+    ],
+    boss_health: 12.0,
+    boss_speed: 0.4,
+    // Plated all round, nothing hits it at full strength
+    boss_resist: Resistances {
+        kinetic: 0.2,
+        energy: 0.2,
+        pulse: 0.2,
+    },
+    boss_credits: 525,
+    boss_base_damage: 50,
+    boss_attack: TurretAttack {
+        damage: 0.35,
+        range: 2.5,
+        interval: 1.0,
+    },
+    //
+    starting_credits: 500,
+    allow_selling: true,
+    // Rebuilding the maze is part of the game, so walls come back in full
+    wall_cost: 20,
+    wall_refund: 1.0,
+    undo_grace: 5.0,
+    move_fee: 0.2,
     //
-    credits_for_kill: 25,
+    interest_rate: 0.02,
+    interest_cap: 40,
+    no_leak_bonus: 15,
+    economy_turret_income: 10,
+    economy_turret_interval: 5.0,
+    //
+    health_curve_exponent: 1.32,
+    speed_curve_scale: 0.1,
+    spawn_cut_scale: 0.3,
+    //
+    base: BaseSettings {
+        hit_points: 100,
+        regen_per_level: 1,
+    },
+    base_shield_cost: 150,
+    base_shield_amount: 20,
+    base_shield_max: 60,
 };
 
+/// Enemy speed multiplier for the fast enemies modifier
+const FAST_ENEMIES_SPEED: f32 = 1.5;
+
+impl GameSettings {
+    /// Rules for a run: its difficulty preset and modifiers applied on top of `GAMESETTINGS`
+    pub fn for_run(run: &RunConfig) -> GameSettings {
+        let mut s = GAMESETTINGS.clone();
+        match run.difficulty {
+            Difficulty::Easy => {
+                s.base = BaseSettings {
+                    hit_points: 150,
+                    regen_per_level: 2,
+                };
+                s.starting_credits = 750;
+                s.health_curve_exponent = 1.26;
+                s.spawn_cut_scale = 0.25;
+            }
+            Difficulty::Normal => (),
+            Difficulty::Hard => {
+                s.base = BaseSettings {
+                    hit_points: 60,
+                    regen_per_level: 0,
+                };
+                s.starting_credits = 400;
+                s.health_curve_exponent = 1.38;
+                s.spawn_cut_scale = 0.35;
+            }
+            // No milestone bosses, the curves just keep climbing
+            Difficulty::Endless => {
+                s.boss_levels = &[];
+                s.base.regen_per_level = 3;
+                s.health_curve_exponent = 1.42;
+            }
+        }
+
+        let m = run.modifiers;
+        if m.double_flyers {
+            s.flying_enemy_spawn_speed *= 0.5;
+            s.flying_enemy_max_spawn_speed *= 0.5;
+        }
+        if m.no_selling {
+            s.allow_selling = false;
+        }
+        if m.half_credits {
+            s.rolling_enemy_credits /= 2;
+            s.rolling_enemy_2_credits /= 2;
+            s.flying_enemy_credits /= 2;
+            s.boss_credits /= 2;
+            s.starting_credits /= 2;
+            s.interest_cap /= 2;
+            s.no_leak_bonus /= 2;
+            s.economy_turret_income /= 2;
+        }
+        if m.fast_enemies {
+            s.rolling_enemy_speed *= FAST_ENEMIES_SPEED;
+            s.rolling_enemy_2_speed *= FAST_ENEMIES_SPEED;
+            s.flying_enemy_speed *= FAST_ENEMIES_SPEED;
+            s.boss_speed *= FAST_ENEMIES_SPEED;
+            s.speed_curve_scale *= FAST_ENEMIES_SPEED;
+        }
+        s
+    }
+}
+
 impl PlayerState {
-    pub fn enemy_speed_boost(&self) -> f32 {
-        self.level.powf(0.4) * 0.1
+    pub fn enemy_speed_boost(&self, settings: &GameSettings) -> f32 {
+        self.level.powf(0.4) * settings.speed_curve_scale
     }
 
-    pub fn spawn_rate_cut(&self) -> f32 {
-        self.level.powf(0.4) * 0.3
+    pub fn spawn_rate_cut(&self, settings: &GameSettings) -> f32 {
+        self.level.powf(0.4) * settings.spawn_cut_scale
     }
 
-    pub fn enemy_health_mult(&self) -> f32 {
+    pub fn enemy_health_mult(&self, settings: &GameSettings) -> f32 {
+        let curve = self.level.powf(settings.health_curve_exponent) + 1.0;
         if self.level < 50.0 {
-            (self.level.powf(1.32) + 1.0) / 2.0
+            curve / 2.0
         } else {
-            (self.level.powf(1.32) + 1.0) / (2.0 - (self.level - 50.0) * 0.5).max(1.0)
+            curve / (2.0 - (self.level - 50.0) * 0.5).max(1.0)
         }
     }
 
@@ -92,36 +330,135 @@ impl PlayerState {
     }
 
     pub fn alive(&self) -> bool {
-        self.health > 0.0
+        self.health > 0
+    }
+
+    pub fn new(settings: &GameSettings) -> Self {
+        PlayerState {
+            credits: settings.starting_credits,
+            health: settings.base.hit_points,
+            max_health: settings.base.hit_points,
+            base_regen: settings.base.regen_per_level,
+            ..default()
+        }
+    }
+
+    /// An enemy reached the base
+    pub fn leak(&mut self, kind: EnemyKind, settings: &GameSettings) {
+        let damage = kind.base_damage(settings);
+        let absorbed = damage.min(self.shield);
+        self.shield -= absorbed;
+        self.health -= damage - absorbed;
+        self.leaks.push(Leak {
+            step: self.step,
+            kind,
+            damage,
+        });
+        self.leaked_this_level = true;
+    }
+
+    /// Interest the banked credits would earn at the next level boundary
+    pub fn interest(&self, settings: &GameSettings) -> u64 {
+        ((self.credits as f32 * settings.interest_rate) as u64).min(settings.interest_cap)
+    }
+
+    /// Whether a placement is still inside the undo window
+    pub fn can_undo(&self, placement: &Placement, settings: &GameSettings) -> bool {
+        let grace_steps = (settings.undo_grace / TIMESTEP) as u64;
+        placement.level == self.level || self.step - placement.step <= grace_steps
+    }
+
+    /// Forgets placements whose undo window has closed, it never opens again
+    pub fn prune_placements(&mut self, settings: &GameSettings) {
+        let placements = std::mem::take(&mut self.placements);
+        self.placements = placements
+            .into_iter()
+            .filter(|placement| self.can_undo(placement, settings))
+            .collect();
+    }
+
+    /// Action placing the selected piece on a cell
+    pub fn place_action(&self, ls: IVec2) -> Option<Action> {
+        let (x, y) = (ls.x as u8, ls.y as u8);
+        if self.wall_mode {
+            return Some(Action::WallPlace(x, y));
+        }
+        Some(match self.turret_to_place? {
+            Turret::Blaster => Action::BlasterPlace(x, y),
+            Turret::Laser => Action::LaserPlace(x, y),
+            Turret::Wave => Action::WavePlace(x, y),
+            Turret::Economy => Action::EconomyPlace(x, y),
+        })
+    }
+
+    pub fn buy_base_shield(&mut self, settings: &GameSettings) {
+        if self.alive()
+            && self.credits >= settings.base_shield_cost
+            && self.shield < settings.base_shield_max
+        {
+            self.credits -= settings.base_shield_cost;
+            self.shield = (self.shield + settings.base_shield_amount).min(settings.base_shield_max);
+        }
     }
 }
 
 impl Default for PlayerState {
     fn default() -> Self {
+        let base = GAMESETTINGS.base;
         PlayerState {
-            credits: 500,
+            credits: GAMESETTINGS.starting_credits,
             turret_to_place: None,
             kills: 0,
-            health: 1.0,
+            health: base.hit_points,
+            max_health: base.hit_points,
+            base_regen: base.regen_per_level,
+            shield: 0,
+            leaks: Vec::new(),
+            placements: Vec::new(),
+            leaked_this_level: false,
             sell_mode: false,
+            repair_mode: false,
+            wall_mode: false,
+            move_mode: false,
             blaster_upgrade: 1.0,
             laser_upgrade: 1.0,
             wave_upgrade: 1.0,
             level_time: 0.0,
             level: 0.0,
-            time_multiplier: 1.0,
             step: 0,
         }
     }
 }
 
-pub fn set_level(mut player: ResMut<PlayerState>) {
+pub fn set_level(
+    mut player: ResMut<PlayerState>,
+    settings: Res<GameSettings>,
+    pref: Res<Preferences>,
+    game_recorder: Res<GameRecorder>,
+    mut action_queue: ResMut<ActionQueue>,
+) {
     if !player.alive() {
         return;
     }
     player.level_time += TIMESTEP;
-    player.level = (player.level_time / 10.0).floor();
+    let level = (player.level_time / 10.0).floor();
+    if level > player.level {
+        if player.health < player.max_health {
+            player.health = (player.health + player.base_regen).min(player.max_health);
+        }
+        player.credits += player.interest(&settings);
+        if !player.leaked_this_level {
+            player.credits += settings.no_leak_bonus;
+        }
+        player.leaked_this_level = false;
+        // Paused like the player would, in time for this step's actions. Replays play straight on
+        if pref.auto_pause && !game_recorder.play {
+            action_queue.push(Action::GamePause);
+        }
+    }
+    player.level = level;
     player.step += 1;
+    player.prune_placements(&settings);
 }
 
 pub fn setup_player(
@@ -148,10 +485,26 @@ pub fn mouse_interact(
     intersections: Query<&Intersection<MyRaycastSet>>,
     b: Res<GameBoard>,
     buttons: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
     mut game_cursor: Query<&mut Transform, With<GameCursor>>,
     player: Res<PlayerState>,
     mut action_queue: ResMut<ActionQueue>,
+    mut egui_context: ResMut<EguiContext>,
+    mut drag_start: Local<Option<IVec2>>,
+    mut move_from: Local<Option<IVec2>>,
 ) {
+    if !player.move_mode {
+        *move_from = None;
+    }
+    // A drag released off the board is dropped
+    if !buttons.pressed(MouseButton::Left) && !buttons.just_released(MouseButton::Left) {
+        *drag_start = None;
+    }
+    // Clicks on the panels and overlays are theirs, not the board's
+    let ctx = egui_context.ctx_mut();
+    if ctx.wants_pointer_input() || ctx.is_pointer_over_area() {
+        return;
+    }
     let mut cursor_pos = None;
     for intersection in &intersections {
         //info!(
@@ -170,24 +523,150 @@ pub fn mouse_interact(
         let p = b.ls_to_ws_vec3(b.ws_vec3_to_ls(cursor_pos));
         trans.translation = p + vec3(0.0, -0.4, 0.0);
     }
-    if buttons.just_pressed(MouseButton::Left) && (cursor_pos.y - 0.0).abs() < 0.1 {
-        let idx = b.ls_to_idx(b.ws_vec3_to_ls(cursor_pos));
-        let ls_p = b.idx_to_ls(idx);
+    if (cursor_pos.y - 0.0).abs() >= 0.1 {
+        return;
+    }
+    let idx = b.ls_to_idx(b.ws_vec3_to_ls(cursor_pos));
+    let ls_p = b.idx_to_ls(idx);
+    if buttons.just_pressed(MouseButton::Left) {
         if player.sell_mode {
             action_queue.push(Action::SellTurret(ls_p.x as u8, ls_p.y as u8));
-        } else if let Some(selected_turret) = player.turret_to_place {
-            match selected_turret {
-                Turret::Blaster => {
-                    action_queue.push(Action::BlasterPlace(ls_p.x as u8, ls_p.y as u8));
-                }
-                Turret::Laser => {
-                    action_queue.push(Action::LaserPlace(ls_p.x as u8, ls_p.y as u8));
+        } else if player.repair_mode {
+            action_queue.push(Action::RepairTurret(ls_p.x as u8, ls_p.y as u8));
+        } else if player.move_mode {
+            // First click picks up a turret, the second drops it
+            if let Some(from) = move_from.take() {
+                action_queue.push(Action::MoveFrom(from.x as u8, from.y as u8));
+                action_queue.push(Action::MoveTo(ls_p.x as u8, ls_p.y as u8));
+            } else if b.board[idx].turret.is_some() {
+                *move_from = Some(ls_p);
+            }
+        } else {
+            *drag_start = Some(ls_p);
+        }
+    }
+    if buttons.just_released(MouseButton::Left) {
+        if let Some(start) = drag_start.take() {
+            if let Some(place) = player.place_action(start) {
+                action_queue.push(place);
+                // Dragging builds a line along the longer axis, or a rectangle with shift held
+                let delta = ls_p - start;
+                let end = if keys.any_pressed([KeyCode::LShift, KeyCode::RShift]) {
+                    ls_p
+                } else if delta.x.abs() >= delta.y.abs() {
+                    ivec2(ls_p.x, start.y)
+                } else {
+                    ivec2(start.x, ls_p.y)
+                };
+                if end != start {
+                    action_queue.push(Action::AreaTo(end.x as u8, end.y as u8));
                 }
-                Turret::Wave => {
-                    action_queue.push(Action::WavePlace(ls_p.x as u8, ls_p.y as u8));
+            }
+        }
+    }
+}
+
+/// Seconds a finger has to stay down for a long press
+const LONG_PRESS: f64 = 0.5;
+/// Pixels a finger can drift and still count as a tap or long press
+const TAP_SLOP: f32 = 12.0;
+
+/// Board cell picked with a long press, shown in the sidebar
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct Inspected(pub Option<IVec2>);
+
+/// Finger currently down, for telling taps from long presses
+pub struct TouchPress {
+    id: u64,
+    start: f64,
+    long_pressed: bool,
+}
+
+/// Tap works like a click, a long press inspects the cell or sells in sell mode. Two finger
+/// gestures belong to the camera.
+pub fn touch_interact(
+    intersections: Query<&Intersection<MyRaycastSet>>,
+    b: Res<GameBoard>,
+    touches: Res<Touches>,
+    time: Res<Time>,
+    player: Res<PlayerState>,
+    mut action_queue: ResMut<ActionQueue>,
+    mut inspected: ResMut<Inspected>,
+    mut egui_context: ResMut<EguiContext>,
+    mut press: Local<Option<TouchPress>>,
+    mut move_from: Local<Option<IVec2>>,
+) {
+    if !player.move_mode {
+        *move_from = None;
+    }
+    // Taps on the panels and overlays are theirs, not the board's
+    let ctx = egui_context.ctx_mut();
+    if touches.iter().count() > 1 || ctx.wants_pointer_input() || ctx.is_pointer_over_area() {
+        *press = None;
+        return;
+    }
+    if let Some(touch) = touches.iter_just_pressed().next() {
+        *press = Some(TouchPress {
+            id: touch.id(),
+            start: time.elapsed_seconds_f64(),
+            long_pressed: false,
+        });
+    }
+
+    let cell = intersections
+        .iter()
+        .filter_map(|intersection| intersection.position())
+        .last()
+        .filter(|pos| pos.y.abs() < 0.1)
+        .map(|pos| b.idx_to_ls(b.ls_to_idx(b.ws_vec3_to_ls(*pos))));
+
+    let current = match press.as_mut() {
+        Some(current) => current,
+        None => return,
+    };
+    if let Some(touch) = touches.get_pressed(current.id) {
+        let held = time.elapsed_seconds_f64() - current.start;
+        if !current.long_pressed && held > LONG_PRESS && touch.distance().length() < TAP_SLOP {
+            current.long_pressed = true;
+            if let Some(ls_p) = cell {
+                if player.sell_mode {
+                    action_queue.push(Action::SellTurret(ls_p.x as u8, ls_p.y as u8));
+                } else {
+                    **inspected = Some(ls_p);
                 }
-            };
+            }
+        }
+    } else if let Some(touch) = touches.get_released(current.id) {
+        let tap = !current.long_pressed && touch.distance().length() < TAP_SLOP;
+        *press = None;
+        let ls_p = match cell {
+            Some(ls_p) if tap => ls_p,
+            _ => return,
+        };
+        if player.sell_mode {
+            action_queue.push(Action::SellTurret(ls_p.x as u8, ls_p.y as u8));
+        } else if player.repair_mode {
+            action_queue.push(Action::RepairTurret(ls_p.x as u8, ls_p.y as u8));
+        } else if player.move_mode {
+            if let Some(from) = move_from.take() {
+                action_queue.push(Action::MoveFrom(from.x as u8, from.y as u8));
+                action_queue.push(Action::MoveTo(ls_p.x as u8, ls_p.y as u8));
+            } else if b.board[b.ls_to_idx(ls_p)].turret.is_some() {
+                *move_from = Some(ls_p);
+            }
+        } else if let Some(place) = player.place_action(ls_p) {
+            action_queue.push(place);
         }
+    } else {
+        // Cancelled by the system
+        *press = None;
+    }
+}
+
+pub fn keyboard_interact(keys: Res<Input<KeyCode>>, mut action_queue: ResMut<ActionQueue>) {
+    let ctrl = keys.any_pressed([KeyCode::LControl, KeyCode::RControl]);
+    if ctrl && keys.just_pressed(KeyCode::Z) {
+        action_queue.push(Action::Undo);
     }
 }
 
@@ -196,11 +675,22 @@ pub struct GameCursor;
 
 pub fn update_raycast_with_cursor(
     mut cursor: EventReader<CursorMoved>,
+    mut touch_events: EventReader<TouchInput>,
+    windows: Res<Windows>,
     mut query: Query<&mut RaycastSource<MyRaycastSet>>,
 ) {
-    // Grab the most recent cursor event if it exists:
-    let cursor_position = match cursor.iter().last() {
-        Some(cursor_moved) => cursor_moved.position,
+    // Grab the most recent cursor or touch event if it exists:
+    let cursor_position = cursor
+        .iter()
+        .last()
+        .map(|cursor_moved| cursor_moved.position);
+    let touch_position = touch_events.iter().last().and_then(|touch| {
+        // Touches start at the top of the window, cursor positions at the bottom
+        let height = windows.get_primary()?.height();
+        Some(vec2(touch.position.x, height - touch.position.y))
+    });
+    let cursor_position = match touch_position.or(cursor_position) {
+        Some(position) => position,
         None => return,
     };
 
@@ -208,3 +698,41 @@ pub fn update_raycast_with_cursor(
         pick_source.cast_method = RaycastMethod::Screenspace(cursor_position);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::Modifiers;
+
+    fn run(difficulty: Difficulty, modifiers: Modifiers) -> RunConfig {
+        RunConfig {
+            difficulty,
+            modifiers,
+            ..RunConfig::default()
+        }
+    }
+
+    #[test]
+    fn normal_run_is_the_baseline() {
+        let s = GameSettings::for_run(&run(Difficulty::Normal, Modifiers::default()));
+        assert_eq!(s.starting_credits, GAMESETTINGS.starting_credits);
+        assert_eq!(s.base.hit_points, GAMESETTINGS.base.hit_points);
+        assert!(s.allow_selling);
+    }
+
+    #[test]
+    fn modifiers_apply_on_top_of_difficulty() {
+        let modifiers = Modifiers {
+            no_selling: true,
+            half_credits: true,
+            ..Modifiers::default()
+        };
+        let s = GameSettings::for_run(&run(Difficulty::Easy, modifiers));
+        assert!(!s.allow_selling);
+        assert_eq!(s.starting_credits, 750 / 2);
+        assert_eq!(s.base.hit_points, 150);
+        assert!(GameSettings::for_run(&run(Difficulty::Endless, modifiers))
+            .boss_levels
+            .is_empty());
+    }
+}
@@ -0,0 +1,182 @@
+use bytecheck::CheckBytes;
+use rkyv::{Archive, Deserialize, Serialize};
+
+#[derive(Archive, Deserialize, Serialize, Clone, Copy, Eq, PartialEq, Default, Debug)]
+#[archive(compare(PartialEq))]
+#[archive_attr(derive(CheckBytes))]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+    Endless,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [
+        Difficulty::Easy,
+        Difficulty::Normal,
+        Difficulty::Hard,
+        Difficulty::Endless,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "EASY",
+            Difficulty::Normal => "NORMAL",
+            Difficulty::Hard => "HARD",
+            Difficulty::Endless => "ENDLESS",
+        }
+    }
+}
+
+/// Optional rule changes picked before a run, applied by `GameSettings::for_run`
+#[derive(Archive, Deserialize, Serialize, Clone, Copy, Eq, PartialEq, Default, Debug)]
+#[archive(compare(PartialEq))]
+#[archive_attr(derive(CheckBytes))]
+pub struct Modifiers {
+    pub double_flyers: bool,
+    pub no_selling: bool,
+    pub half_credits: bool,
+    pub fast_enemies: bool,
+}
+
+impl Modifiers {
+    pub fn names(&self) -> Vec<&'static str> {
+        [
+            (self.double_flyers, "2X FLYERS"),
+            (self.no_selling, "NO SELLING"),
+            (self.half_credits, "HALF CREDITS"),
+            (self.fast_enemies, "FAST ENEMIES"),
+        ]
+        .into_iter()
+        .filter_map(|(on, name)| on.then_some(name))
+        .collect()
+    }
+}
+
+const TUTORIAL_SEED: u64 = 0x7075_0a1a;
+
+/// Everything needed to reproduce the start of a run. Stored at the head of each recording.
+#[derive(Archive, Deserialize, Serialize, Clone, Copy, Eq, PartialEq, Default, Debug)]
+#[archive(compare(PartialEq))]
+#[archive_attr(derive(CheckBytes))]
+pub struct RunConfig {
+    pub seed: u64,
+    /// Day number (days since 1970-01-01) for daily challenge runs, which also get board obstacles
+    pub daily: Option<u32>,
+    pub difficulty: Difficulty,
+    pub modifiers: Modifiers,
+}
+
+impl RunConfig {
+    pub fn random(difficulty: Difficulty, modifiers: Modifiers) -> Self {
+        RunConfig {
+            seed: mix_seed(now_unix_millis()),
+            daily: None,
+            difficulty,
+            modifiers,
+        }
+    }
+
+    /// The guided first game, always on the same seed so the steps play out as written
+    pub fn tutorial() -> Self {
+        RunConfig {
+            seed: TUTORIAL_SEED,
+            daily: None,
+            difficulty: Difficulty::Normal,
+            modifiers: Modifiers::default(),
+        }
+    }
+
+    /// Tutorial runs are practice, they stay off the high score table
+    pub fn is_tutorial(&self) -> bool {
+        self.seed == TUTORIAL_SEED && self.daily.is_none()
+    }
+
+    /// Same seed, board and rules for everyone playing on the same (UTC) day
+    pub fn daily() -> Self {
+        let day = (now_unix_millis() / 86_400_000) as u32;
+        RunConfig {
+            seed: mix_seed(day as u64 ^ 0xdeca_face),
+            daily: Some(day),
+            difficulty: Difficulty::Normal,
+            modifiers: Modifiers::default(),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self.daily {
+            Some(day) => {
+                let (y, m, d) = civil_from_days(day as i64);
+                format!("DAILY {}-{:02}-{:02}", y, m, d)
+            }
+            None if self.is_tutorial() => "TUTORIAL".to_string(),
+            None => format!("SEED {:016X}", self.seed),
+        }
+    }
+
+    pub fn rules_label(&self) -> String {
+        let mut label = self.difficulty.name().to_string();
+        for name in self.modifiers.names() {
+            label.push_str(" +");
+            label.push_str(name);
+        }
+        label
+    }
+}
+
+// splitmix64 finalizer, so nearby inputs give unrelated seeds
+fn mix_seed(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now_unix_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn now_unix_millis() -> u64 {
+    js_sys::Date::now() as u64
+}
+
+// Days since 1970-01-01 to (year, month, day), from http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(z: i64) -> (i64, u32, u32) {
+    let z = z + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn civil_from_days_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(civil_from_days(19000), (2022, 1, 8));
+    }
+
+    #[test]
+    fn mix_seed_spreads_neighbours() {
+        assert_ne!(mix_seed(1), mix_seed(2));
+        assert_eq!(mix_seed(1), mix_seed(1));
+        // Consecutive days differ in about half their bits
+        assert!((mix_seed(19000) ^ mix_seed(19001)).count_ones() > 16);
+    }
+}
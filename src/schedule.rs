@@ -1,37 +1,164 @@
 use std::time::Duration;
 
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::Instant};
 use bevy_mod_raycast::{DefaultRaycastingPlugin, RaycastSystem};
 use bevy_system_graph::SystemGraph;
 use iyes_loopless::prelude::*;
 
 use crate::{
-    action::*, enemies::*, game_state_run_level_unpaused, player::*, restart_game, turrets::*,
-    GameState,
+    abilities::{absorb_shield_damage, heal_nearby, update_tunnels},
+    action::*,
+    assets::ModelAssets,
+    audio::AudioEvents,
+    board::GameBoard,
+    boss::{spawn_boss, update_bosses},
+    enemies::*,
+    game_state_run_level_unpaused,
+    player::*,
+    restart_game,
+    status::tick_status_effects,
+    steering::steer_flying_enemies,
+    tactical::PlannedActions,
+    turrets::*,
+    ui::Preferences,
+    GameRng, GameState, PausedState, RestartGame,
 };
 
 pub const TIMESTEP_MILLI: u64 = 16;
 pub const TIMESTEP: f32 = 0.016;
 pub const TIMESTEP_SEC_F64: f64 = 0.016;
 
+/// Longest frame time that is turned into simulation time, so a hitch or a backgrounded tab
+/// doesn't queue up seconds of steps
+const MAX_FRAME_DELTA: f64 = 0.1;
+/// Steps that may stay owed after running out of frame budget. Anything beyond is dropped, so a
+/// machine that can't keep up runs the game slower instead of spiraling.
+const MAX_BACKLOG_STEPS: f64 = 4.0;
+/// Wall time per frame the simulation may use before yielding to rendering
+const FRAME_BUDGET: Duration = Duration::from_millis(12);
+
+#[derive(Resource)]
+pub struct SimSpeed {
+    /// Simulated seconds per real second
+    pub multiplier: f64,
+    /// Run as many steps as fit in the frame budget, for fast-forwarding replays
+    pub max_speed: bool,
+}
+
+impl Default for SimSpeed {
+    fn default() -> Self {
+        SimSpeed {
+            multiplier: 1.0,
+            max_speed: false,
+        }
+    }
+}
+
+/// Runs the simulation stage in whole `TIMESTEP` steps, as many per frame as the game speed calls
+/// for. The step length never changes, only how many steps are run.
+struct SimStage {
+    stage: SystemStage,
+    pending: f64,
+}
+
+impl Stage for SimStage {
+    fn run(&mut self, world: &mut World) {
+        if *world.resource::<CurrentState<GameState>>() != CurrentState(GameState::RunLevel) {
+            self.pending = 0.0;
+            return;
+        }
+        // While paused only the actions run, so the player can still unpause and plan. One step
+        // covers that, and no time is owed for the pause.
+        if *world.resource::<CurrentState<PausedState>>() != CurrentState(PausedState::Unpaused) {
+            self.pending = 0.0;
+            self.stage.run(world);
+            return;
+        }
+        let delta = world.resource::<Time>().delta_seconds_f64();
+        let speed = world.resource::<SimSpeed>();
+        let max_speed = speed.max_speed;
+        if !max_speed {
+            self.pending += delta.min(MAX_FRAME_DELTA) * speed.multiplier;
+        }
+
+        let start = Instant::now();
+        loop {
+            let out_of_budget = start.elapsed() >= FRAME_BUDGET;
+            if max_speed {
+                if out_of_budget {
+                    self.pending = 0.0;
+                    break;
+                }
+            } else {
+                if self.pending < TIMESTEP_SEC_F64 {
+                    break;
+                }
+                if out_of_budget {
+                    self.pending = self.pending.min(TIMESTEP_SEC_F64 * MAX_BACKLOG_STEPS);
+                    break;
+                }
+                self.pending -= TIMESTEP_SEC_F64;
+            }
+            let before = world.resource::<PlayerState>();
+            let (level, step) = (before.level, before.step);
+            self.stage.run(world);
+            // Paused, out of the level or game over: fast-forwarding has nothing left to skip
+            if max_speed && world.resource::<PlayerState>().step == step {
+                self.pending = 0.0;
+                break;
+            }
+            // `set_level` queued a pause at the level boundary, the rest of the frame's steps would
+            // overshoot it
+            if world.resource::<Preferences>().auto_pause
+                && !world.resource::<GameRecorder>().play
+                && world.resource::<PlayerState>().level > level
+            {
+                self.pending = 0.0;
+                break;
+            }
+        }
+    }
+}
+
 pub(crate) fn setup_schedule(app: &mut bevy::prelude::App) {
     let mut fixed_update_stage = SystemStage::parallel();
 
     app.add_system_set(
         ConditionSet::new()
             .run_in_state(GameState::RunLevel)
+            .label("INPUT")
             .with_system(mouse_interact)
+            .with_system(keyboard_interact)
+            .with_system(touch_interact)
             .into(),
     );
 
     app.add_plugin(DefaultRaycastingPlugin::<MyRaycastSet>::default())
         .insert_resource(PlayerState::default())
+        .insert_resource(Inspected::default())
         .add_enter_system(GameState::RunLevel, setup_player)
         .add_system_to_stage(
             CoreStage::First,
             update_raycast_with_cursor.before(RaycastSystem::BuildRays::<MyRaycastSet>),
         );
 
+    add_simulation_systems(&mut fixed_update_stage);
+
+    app.insert_resource(ActionQueue::default());
+    app.insert_resource(SimSpeed::default());
+
+    app.add_stage_after(
+        CoreStage::Update,
+        "my_fixed_update",
+        SimStage {
+            stage: fixed_update_stage,
+            pending: 0.0,
+        },
+    );
+}
+
+/// Systems that advance the game by one fixed timestep
+fn add_simulation_systems(fixed_update_stage: &mut SystemStage) {
     fixed_update_stage.add_system_set(
         Into::<SystemSet>::into(SystemGraph::new().root(set_level).graph())
             .with_run_criteria(game_state_run_level_unpaused)
@@ -42,14 +169,21 @@ pub(crate) fn setup_schedule(app: &mut bevy::prelude::App) {
     fixed_update_stage.add_system_set(
         Into::<SystemSet>::into(
             SystemGraph::new()
-                .root(destroy_enemies)
+                .root(absorb_shield_damage)
+                .then(destroy_enemies)
+                .then(update_bosses)
+                .then(spawn_boss)
                 .then(spawn_rolling_enemy)
                 .then(spawn_rolling_enemy2)
                 .then(spawn_flying_enemy)
                 .then(update_enemy_paths)
                 .then(update_board_has_enemy)
+                .then(tick_status_effects)
+                .then(heal_nearby)
                 .then(move_enemy_along_path)
-                .then(move_flying_enemy)
+                .then(update_tunnels)
+                .then(attack_turrets)
+                .then(steer_flying_enemies)
                 .then(check_enemy_at_dest)
                 .then(check_flying_enemy_at_dest)
                 .then(update_enemy_postgame_paths)
@@ -68,7 +202,9 @@ pub(crate) fn setup_schedule(app: &mut bevy::prelude::App) {
                 .then(bobble_shockwave_spheres)
                 .then(position_caps)
                 .then(reset_turret_gfx)
+                .then(wreck_destroyed_turrets)
                 .then(turret_fire)
+                .then(generate_income)
                 .then(blaster_point_at_enemy)
                 .graph(),
         )
@@ -77,8 +213,6 @@ pub(crate) fn setup_schedule(app: &mut bevy::prelude::App) {
         .after("STEP ENEMIES"),
     );
 
-    app.insert_resource(ActionQueue::default());
-    app.insert_resource(GameRecorder::default());
     fixed_update_stage.add_system_set(
         ConditionSet::new()
             .run_in_state(GameState::RunLevel)
@@ -96,11 +230,78 @@ pub(crate) fn setup_schedule(app: &mut bevy::prelude::App) {
             .with_system(restart_game)
             .into(),
     );
+}
 
-    app.add_stage_after(
-        CoreStage::Update,
-        "my_fixed_update",
-        FixedTimestepStage::new(Duration::from_millis(TIMESTEP_MILLI), "main")
-            .with_stage(fixed_update_stage),
-    );
+/// Upper bound so a replay of a game that never ends can't hang the caller (~55 min of game time)
+const MAX_HEADLESS_STEPS: u64 = 200_000;
+
+/// A recording played back from a fresh game without any rendering, audio or windowing. Steps a
+/// slice at a time so it can run alongside the game without holding up a frame.
+pub struct HeadlessReplay {
+    world: World,
+    stage: SystemStage,
+}
+
+impl HeadlessReplay {
+    pub fn new(actions: ActionRecording) -> Self {
+        let mut world = World::new();
+        world.insert_resource(CurrentState(GameState::RunLevel));
+        world.insert_resource(CurrentState(PausedState::Unpaused));
+        world.insert_resource(GameBoard::for_run(&actions.run));
+        world.insert_resource(RestartGame::default());
+        world.insert_resource(GameRng::new(actions.run.seed));
+        let settings = GameSettings::for_run(&actions.run);
+        world.insert_resource(PlayerState::new(&settings));
+        world.insert_resource(settings);
+        world.insert_resource(LastSpawns::default());
+        world.insert_resource(ModelAssets::default());
+        world.insert_resource(Preferences::default());
+        world.insert_resource(AudioEvents::default());
+        world.insert_resource(ActionQueue::default());
+        world.insert_resource(PlannedActions::default());
+        world.insert_resource(SimSpeed::default());
+        world.insert_resource(GameRecorder {
+            actions,
+            disable_rec: true,
+            play: true,
+            play_head: 0,
+        });
+
+        let mut stage = SystemStage::single_threaded();
+        add_simulation_systems(&mut stage);
+
+        HeadlessReplay { world, stage }
+    }
+
+    pub fn player(&self) -> &PlayerState {
+        self.world.resource::<PlayerState>()
+    }
+
+    /// The game has ended, or run long enough that it's not going to
+    pub fn finished(&self) -> bool {
+        let player = self.player();
+        !player.alive() || player.step >= MAX_HEADLESS_STEPS
+    }
+
+    /// Steps until the game is finished or `budget` has passed. Returns whether it finished.
+    pub fn run_for(&mut self, budget: Duration) -> bool {
+        let start = Instant::now();
+        while !self.finished() {
+            if start.elapsed() >= budget {
+                return false;
+            }
+            self.stage.run(&mut self.world);
+        }
+        true
+    }
+}
+
+/// Plays back a whole recording in one go and returns the final player state
+#[cfg(test)]
+pub fn simulate_replay(actions: ActionRecording) -> PlayerState {
+    let mut replay = HeadlessReplay::new(actions);
+    while !replay.finished() {
+        replay.stage.run(&mut replay.world);
+    }
+    replay.world.remove_resource::<PlayerState>().unwrap()
 }
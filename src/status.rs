@@ -0,0 +1,223 @@
+use bevy::{math::*, prelude::*};
+use iyes_loopless::prelude::*;
+
+use crate::{
+    damage::DamageType,
+    enemies::{EnemyKind, Health},
+    player::GameSettings,
+    schedule::TIMESTEP,
+    GameState,
+};
+
+pub struct StatusPlugin;
+impl Plugin for StatusPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_enter_system(GameState::RunLevel, setup_status_gfx)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::RunLevel)
+                    .with_system(spawn_status_indicators)
+                    .with_system(update_status_indicators)
+                    .into(),
+            );
+    }
+}
+
+/// Slows can't fully stop a unit, that's what stuns are for
+const MAX_SLOW: f32 = 0.8;
+/// After a stun is applied the unit can't be stunned again for this long, so massed blasters
+/// can't lock units in place
+const STUN_IMMUNITY: f32 = 1.0;
+
+/// A timed effect. Reapplying keeps the stronger magnitude and the longer remaining time, so
+/// effects of the same kind never stack additively.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Timed {
+    pub magnitude: f32,
+    pub remaining: f32,
+}
+
+impl Timed {
+    fn apply(&mut self, magnitude: f32, duration: f32) {
+        if self.active() {
+            self.magnitude = self.magnitude.max(magnitude);
+            self.remaining = self.remaining.max(duration);
+        } else {
+            self.magnitude = magnitude;
+            self.remaining = duration;
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.remaining > 0.0
+    }
+
+    fn tick(&mut self) {
+        self.remaining = (self.remaining - TIMESTEP).max(0.0);
+    }
+}
+
+#[derive(Component, Default, Debug)]
+pub struct StatusEffects {
+    /// Fraction of speed removed
+    pub slow: Timed,
+    /// No movement at all, magnitude is unused
+    pub stun: Timed,
+    stun_immunity: Timed,
+    /// Energy damage per second
+    pub burn: Timed,
+    /// Extra fraction of damage taken from every source
+    pub vulnerable: Timed,
+}
+
+impl StatusEffects {
+    pub fn slow(&mut self, amount: f32, duration: f32) {
+        self.slow.apply(amount.min(MAX_SLOW), duration);
+    }
+
+    pub fn stun(&mut self, duration: f32) {
+        if !self.stun_immunity.active() {
+            self.stun.apply(1.0, duration);
+            self.stun_immunity.apply(1.0, duration + STUN_IMMUNITY);
+        }
+    }
+
+    pub fn burn(&mut self, damage_per_sec: f32, duration: f32) {
+        self.burn.apply(damage_per_sec, duration);
+    }
+
+    pub fn vulnerable(&mut self, amount: f32, duration: f32) {
+        self.vulnerable.apply(amount, duration);
+    }
+
+    pub fn speed_mult(&self) -> f32 {
+        if self.stun.active() {
+            0.0
+        } else if self.slow.active() {
+            1.0 - self.slow.magnitude
+        } else {
+            1.0
+        }
+    }
+
+    pub fn damage_taken_mult(&self) -> f32 {
+        if self.vulnerable.active() {
+            1.0 + self.vulnerable.magnitude
+        } else {
+            1.0
+        }
+    }
+}
+
+pub(crate) fn tick_status_effects(
+    mut enemies: Query<(&mut StatusEffects, &mut Health, &EnemyKind)>,
+    settings: Res<GameSettings>,
+) {
+    for (mut effects, mut health, kind) in enemies.iter_mut() {
+        if effects.burn.active() {
+            health.0 -= effects.burn.magnitude
+                * TIMESTEP
+                * kind
+                    .resistances(&settings)
+                    .effectiveness(DamageType::Energy)
+                * effects.damage_taken_mult();
+        }
+        effects.slow.tick();
+        effects.stun.tick();
+        effects.stun_immunity.tick();
+        effects.burn.tick();
+        effects.vulnerable.tick();
+    }
+}
+
+#[derive(Resource)]
+struct StatusGfx {
+    mesh: Handle<Mesh>,
+    slow: Handle<StandardMaterial>,
+    stun: Handle<StandardMaterial>,
+    burn: Handle<StandardMaterial>,
+    vulnerable: Handle<StandardMaterial>,
+}
+
+#[derive(Component)]
+struct StatusIndicator;
+
+fn setup_status_gfx(
+    mut com: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let mut material = |color: Color| {
+        materials.add(StandardMaterial {
+            base_color: Color::BLACK,
+            emissive: color,
+            unlit: true,
+            ..default()
+        })
+    };
+    com.insert_resource(StatusGfx {
+        mesh: meshes.add(Mesh::from(shape::UVSphere {
+            radius: 0.12,
+            ..default()
+        })),
+        slow: material(Color::rgb(0.2, 0.6, 1.0)),
+        stun: material(Color::rgb(1.0, 1.0, 0.3)),
+        burn: material(Color::rgb(1.0, 0.4, 0.0)),
+        vulnerable: material(Color::rgb(0.8, 0.2, 1.0)),
+    });
+}
+
+fn spawn_status_indicators(
+    mut com: Commands,
+    enemies: Query<Entity, Added<StatusEffects>>,
+    gfx: Res<StatusGfx>,
+) {
+    for entity in enemies.iter() {
+        com.entity(entity).add_children(|parent| {
+            parent
+                .spawn(PbrBundle {
+                    mesh: gfx.mesh.clone(),
+                    material: gfx.slow.clone(),
+                    transform: Transform::from_translation(vec3(0.0, 1.2, 0.0)),
+                    visibility: Visibility { is_visible: false },
+                    ..default()
+                })
+                .insert(StatusIndicator);
+        });
+    }
+}
+
+/// Shows the most severe active effect above each enemy
+fn update_status_indicators(
+    mut indicators: Query<
+        (&Parent, &mut Visibility, &mut Handle<StandardMaterial>),
+        With<StatusIndicator>,
+    >,
+    enemies: Query<&StatusEffects>,
+    gfx: Res<StatusGfx>,
+) {
+    for (parent, mut vis, mut material) in indicators.iter_mut() {
+        let effects = if let Ok(effects) = enemies.get(parent.get()) {
+            effects
+        } else {
+            continue;
+        };
+        let shown = if effects.stun.active() {
+            Some(&gfx.stun)
+        } else if effects.burn.active() {
+            Some(&gfx.burn)
+        } else if effects.slow.active() {
+            Some(&gfx.slow)
+        } else if effects.vulnerable.active() {
+            Some(&gfx.vulnerable)
+        } else {
+            None
+        };
+        vis.is_visible = shown.is_some();
+        if let Some(shown) = shown {
+            if *material != *shown {
+                *material = shown.clone();
+            }
+        }
+    }
+}
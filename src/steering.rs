@@ -0,0 +1,178 @@
+use std::f32::consts::TAU;
+
+use bevy::{math::*, prelude::*, utils::HashMap};
+use rand::Rng;
+
+use crate::{
+    enemies::{Enemy, FlyingEnemy},
+    player::GameSettings,
+    schedule::TIMESTEP,
+    status::StatusEffects,
+    GameRng,
+};
+
+/// Flyers per formation, the first one spawned leads
+pub const FORMATION_SIZE: u32 = 5;
+const FORMATION_SPACING: f32 = 0.9;
+const SEPARATION_RADIUS: f32 = 0.7;
+const SEPARATION_STRENGTH: f32 = 1.5;
+/// How quickly velocity turns toward the desired velocity, per second
+const STEER_RATE: f32 = 4.0;
+const ALTITUDE_RATE: f32 = 1.5;
+/// Followers may fly a little faster than their leader to close up gaps
+const FOLLOWER_CATCH_UP: f32 = 1.2;
+/// Horizontal distance from the destination where flyers leave formation and dive
+const DIVE_DISTANCE: f32 = 4.0;
+const WEAVE_AMPLITUDE: f32 = 0.8;
+const WEAVE_FREQUENCY: f32 = 6.0;
+/// Seconds a flyer keeps weaving after it was last targeted
+const EVADE_TIME: f32 = 1.0;
+
+#[derive(Component)]
+pub struct Steering {
+    /// Spawn order, flyers are always processed in this order so results don't depend on query
+    /// iteration order
+    pub id: u64,
+    pub group: u64,
+    pub slot: u32,
+    pub velocity: Vec3,
+    pub cruise_altitude: f32,
+    weave_phase: f32,
+    evade_timer: f32,
+}
+
+impl Steering {
+    pub fn new(id: u64, group: u64, slot: u32, rng: &mut GameRng) -> Self {
+        Steering {
+            id,
+            group,
+            slot,
+            velocity: Vec3::ZERO,
+            cruise_altitude: rng.gen_range(1.5..3.5),
+            weave_phase: rng.gen_range(0.0..TAU),
+            evade_timer: 0.0,
+        }
+    }
+
+    /// Called by turrets that aim at this flyer
+    pub fn targeted(&mut self) {
+        self.evade_timer = EVADE_TIME;
+    }
+}
+
+/// Offset of a formation slot from the leader: a V opening backwards from `forward`
+pub fn formation_offset(slot: u32, forward: Vec3) -> Vec3 {
+    if slot == 0 {
+        return Vec3::ZERO;
+    }
+    let right = forward.cross(Vec3::Y).normalize_or_zero();
+    let row = ((slot + 1) / 2) as f32;
+    let side = if slot % 2 == 1 { -1.0 } else { 1.0 };
+    (-forward + right * side) * row * FORMATION_SPACING
+}
+
+fn flat(v: Vec3) -> Vec3 {
+    vec3(v.x, 0.0, v.z)
+}
+
+pub(crate) fn steer_flying_enemies(
+    mut flyers: Query<(
+        Entity,
+        &mut Transform,
+        &mut Steering,
+        &FlyingEnemy,
+        &Enemy,
+        &StatusEffects,
+    )>,
+    settings: Res<GameSettings>,
+) {
+    let mut snapshot = flyers
+        .iter()
+        .map(|(entity, trans, steer, ..)| {
+            (
+                steer.id,
+                entity,
+                trans.translation,
+                steer.group,
+                steer.slot,
+                steer.velocity,
+            )
+        })
+        .collect::<Vec<_>>();
+    snapshot.sort_by_key(|f| f.0);
+
+    // The lowest surviving slot in each group leads
+    let mut leaders: HashMap<u64, (u32, Vec3, Vec3)> = HashMap::default();
+    for &(_, _, pos, group, slot, velocity) in &snapshot {
+        let leader = leaders.entry(group).or_insert((slot, pos, velocity));
+        if slot < leader.0 {
+            *leader = (slot, pos, velocity);
+        }
+    }
+
+    for &(_, entity, pos, group, slot, _) in &snapshot {
+        let (_, mut trans, mut steer, fly_enemy, enemy, effects) = flyers.get_mut(entity).unwrap();
+        let max_speed = enemy.speed * effects.speed_mult();
+        let to_dest = flat(fly_enemy.dest - pos);
+        let diving = to_dest.length() < DIVE_DISTANCE;
+
+        let (leader_slot, leader_pos, leader_vel) = leaders[&group];
+        let following = !diving && leader_slot != slot;
+        let target = if following {
+            let forward = flat(leader_vel).normalize_or_zero();
+            let forward = if forward == Vec3::ZERO {
+                flat(fly_enemy.dest - leader_pos).normalize_or_zero()
+            } else {
+                forward
+            };
+            leader_pos + formation_offset(slot - leader_slot, forward)
+        } else {
+            fly_enemy.dest
+        };
+
+        let mut desired = flat(target - pos).normalize_or_zero() * max_speed;
+
+        // Hold cruise altitude, then come down so the flyer is low before it reaches the destination
+        let altitude = if diving {
+            steer.cruise_altitude * ((to_dest.length() - 1.0) / DIVE_DISTANCE).max(0.0)
+        } else {
+            steer.cruise_altitude
+        }
+        .max(fly_enemy.dest.y);
+        desired.y = (altitude - pos.y) * ALTITUDE_RATE;
+
+        for &(_, other, other_pos, ..) in &snapshot {
+            if other == entity {
+                continue;
+            }
+            let away = pos - other_pos;
+            let dist = away.length();
+            if dist > 0.0 && dist < SEPARATION_RADIUS {
+                desired += away / dist * (SEPARATION_RADIUS - dist) * SEPARATION_STRENGTH;
+            }
+        }
+
+        if settings.flying_enemy_evasion && steer.evade_timer > 0.0 && !diving {
+            steer.evade_timer -= TIMESTEP;
+            steer.weave_phase += WEAVE_FREQUENCY * TIMESTEP;
+            let right = flat(desired).cross(Vec3::Y).normalize_or_zero();
+            desired += right * steer.weave_phase.sin() * WEAVE_AMPLITUDE * max_speed;
+        }
+
+        let limit = if following {
+            max_speed * FOLLOWER_CATCH_UP
+        } else {
+            max_speed
+        };
+        let velocity = steer
+            .velocity
+            .lerp(desired, (STEER_RATE * TIMESTEP).min(1.0))
+            .clamp_length_max(limit);
+        steer.velocity = velocity;
+        trans.translation += velocity * TIMESTEP;
+        if velocity.length_squared() > 0.0001 {
+            let look = trans.translation + velocity;
+            trans.look_at(look, Vec3::Y);
+        }
+    }
+}
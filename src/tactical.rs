@@ -0,0 +1,178 @@
+use bevy::{math::*, prelude::*};
+use bevy_egui::{egui, egui::Color32, EguiContext};
+use iyes_loopless::prelude::*;
+
+use crate::{
+    action::{area_cells, Action, ActionQueue},
+    board::GameBoard,
+    GameState, PausedState,
+};
+
+pub struct TacticalPlugin;
+impl Plugin for TacticalPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.insert_resource(PlannedActions::default())
+            .add_enter_system(GameState::RunLevel, setup_ghost_gfx)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::RunLevel)
+                    .with_system(update_ghosts)
+                    .into(),
+            )
+            // Anchored inside whatever the HUD and panels leave free
+            .add_system(
+                tactical_overlay
+                    .run_in_state(GameState::RunLevel)
+                    .after("UI PANELS"),
+            );
+    }
+}
+
+/// Building actions made during a pause, carried out in order once the game resumes so credits and
+/// pathing are checked against the board as it is then
+#[derive(Resource, Deref, DerefMut, Default)]
+pub struct PlannedActions(pub Vec<Action>);
+
+impl PlannedActions {
+    /// Whether an action waits for the pause to end instead of happening right away
+    pub fn plans(action: &Action) -> bool {
+        matches!(
+            action,
+            Action::BlasterPlace(..)
+                | Action::WavePlace(..)
+                | Action::LaserPlace(..)
+                | Action::EconomyPlace(..)
+                | Action::WallPlace(..)
+                | Action::AreaTo(..)
+                | Action::MoveFrom(..)
+                | Action::MoveTo(..)
+        )
+    }
+
+    /// Drops the last planned step, along with the action anchoring it
+    pub fn undo(&mut self) {
+        if let Some(Action::AreaTo(..) | Action::MoveTo(..)) = self.0.pop() {
+            self.0.pop();
+        }
+    }
+
+    /// Cells the plan would build on
+    pub fn cells(&self) -> Vec<(u8, u8)> {
+        let mut cells = Vec::new();
+        let mut anchor = None;
+        for action in &self.0 {
+            match action {
+                Action::BlasterPlace(x, y)
+                | Action::WavePlace(x, y)
+                | Action::LaserPlace(x, y)
+                | Action::EconomyPlace(x, y)
+                | Action::WallPlace(x, y) => {
+                    cells.push((*x, *y));
+                    anchor = Some((*x, *y));
+                    continue;
+                }
+                Action::AreaTo(x, y) => {
+                    if let Some(from) = anchor {
+                        cells.extend(area_cells(from, (*x, *y)).into_iter().skip(1));
+                    }
+                }
+                Action::MoveTo(x, y) => cells.push((*x, *y)),
+                _ => (),
+            }
+            anchor = None;
+        }
+        cells
+    }
+}
+
+#[derive(Component)]
+struct Ghost;
+
+#[derive(Resource)]
+struct GhostGfx {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+fn setup_ghost_gfx(
+    mut com: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    com.insert_resource(GhostGfx {
+        mesh: meshes.add(Mesh::from(shape::Box::new(0.8, 0.4, 0.8))),
+        material: materials.add(StandardMaterial {
+            base_color: Color::rgba(0.37, 1.0, 0.66, 0.25),
+            emissive: Color::rgb(0.1, 0.3, 0.2),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        }),
+    });
+}
+
+fn update_ghosts(
+    mut com: Commands,
+    planned: Res<PlannedActions>,
+    ghosts: Query<Entity, With<Ghost>>,
+    b: Res<GameBoard>,
+    gfx: Res<GhostGfx>,
+) {
+    if !planned.is_changed() {
+        return;
+    }
+    for entity in ghosts.iter() {
+        com.entity(entity).despawn_recursive();
+    }
+    for (x, y) in planned.cells() {
+        let pos = b.ls_to_ws_vec3(ivec2(x as i32, y as i32));
+        com.spawn(PbrBundle {
+            mesh: gfx.mesh.clone(),
+            material: gfx.material.clone(),
+            transform: Transform::from_translation(pos + Vec3::Y * 0.2),
+            ..default()
+        })
+        .insert(Ghost);
+    }
+}
+
+fn tactical_overlay(
+    mut egui_context: ResMut<EguiContext>,
+    paused_state: Res<CurrentState<PausedState>>,
+    mut planned: ResMut<PlannedActions>,
+    mut action_queue: ResMut<ActionQueue>,
+) {
+    if *paused_state != CurrentState(PausedState::Paused) {
+        return;
+    }
+    let ctx = egui_context.ctx_mut();
+
+    // Tint the board so it's obvious time is stopped
+    ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Background,
+        egui::Id::new("tactical_tint"),
+    ))
+    .rect_filled(
+        ctx.screen_rect(),
+        0.0,
+        Color32::from_rgba_unmultiplied(0, 20, 40, 70),
+    );
+
+    egui::Area::new("tactical_pause")
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 16.0))
+        .show(ctx, |ui| {
+            ui.visuals_mut().override_text_color = Some(Color32::from_rgb(94, 255, 169));
+            ui.vertical_centered(|ui| {
+                ui.heading("TACTICAL PAUSE");
+                ui.label(&format!("PLANNED {}", planned.cells().len()));
+                ui.horizontal(|ui| {
+                    if ui.button("RESUME").clicked() {
+                        action_queue.push(Action::GamePause);
+                    }
+                    if ui.button("CLEAR PLAN").clicked() {
+                        planned.clear();
+                    }
+                });
+            });
+        });
+}
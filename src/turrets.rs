@@ -11,21 +11,38 @@ use crate::audio::CONTINUOUS_LASER_SOUND;
 use crate::audio::LASER_SOUND;
 use crate::audio::WAVE_SOUND;
 use crate::basic_light;
+use crate::board::GameBoard;
+use crate::damage::DamageType;
+use crate::player::GameSettings;
 use crate::player::PlayerState;
 use crate::schedule::TIMESTEP;
 use crate::schedule::TIMESTEP_MILLI;
+use crate::status::StatusEffects;
+use crate::steering::Steering;
 use crate::ui::Preferences;
 
 use crate::{
     assets::ModelAssets,
-    enemies::{Enemy, Health},
+    enemies::{Enemy, EnemyKind, Health, Layer},
 };
 
+// Status effects applied on hit, as (magnitude, seconds)
+const WAVE_SLOW: (f32, f32) = (0.4, 1.5);
+const WAVE_VULNERABLE: (f32, f32) = (0.2, 1.5);
+const LASER_BURN: (f32, f32) = (0.05, 2.0);
+const BLASTER_STUN: f32 = 0.15;
+/// How far a wrecked turret sinks into its cell
+const WRECK_SINK: f32 = 0.35;
+/// Fraction of the turret's cost it takes to repair it from zero health
+const REPAIR_COST: f32 = 0.6;
+
 #[derive(Clone, Copy, Component, PartialEq, Eq)]
 pub enum Turret {
     Blaster,
     Laser,
     Wave,
+    /// Doesn't fight, pays out credits while it stands
+    Economy,
 }
 
 impl Turret {
@@ -34,6 +51,107 @@ impl Turret {
             Turret::Blaster => 100,
             Turret::Wave => 200,
             Turret::Laser => 300,
+            Turret::Economy => 250,
+        }
+    }
+
+    pub fn max_health(&self) -> f32 {
+        match self {
+            Turret::Blaster => 1.0,
+            Turret::Wave => 1.5,
+            Turret::Laser => 0.8,
+            Turret::Economy => 0.6,
+        }
+    }
+
+    /// Credits to bring the turret back to full health, paid in proportion to the damage taken
+    pub fn repair_cost(&self, health: &TurretHealth) -> u64 {
+        let missing = 1.0 - health.current.max(0.0) / health.max;
+        (self.cost() as f32 * REPAIR_COST * missing).ceil() as u64
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Turret::Blaster => "BLASTER",
+            Turret::Laser => "LASER",
+            Turret::Wave => "WAVE",
+            Turret::Economy => "ECONOMY",
+        }
+    }
+
+    pub fn move_fee(&self, settings: &GameSettings) -> u64 {
+        (self.cost() as f32 * settings.move_fee).ceil() as u64
+    }
+
+    pub fn damage_type(&self) -> Option<DamageType> {
+        match self {
+            Turret::Blaster => Some(DamageType::Kinetic),
+            Turret::Laser => Some(DamageType::Energy),
+            Turret::Wave => Some(DamageType::Pulse),
+            Turret::Economy => None,
+        }
+    }
+
+    pub fn targets(&self) -> TargetLayers {
+        match self {
+            Turret::Blaster => TargetLayers::Both,
+            Turret::Laser => TargetLayers::Both,
+            Turret::Wave => TargetLayers::Ground,
+            Turret::Economy => TargetLayers::None,
+        }
+    }
+
+    /// Damage multiplier against an enemy type, from its resistances and layer
+    pub fn effectiveness(&self, kind: EnemyKind, settings: &GameSettings) -> f32 {
+        match self.damage_type() {
+            Some(damage_type) => {
+                kind.resistances(settings).effectiveness(damage_type)
+                    * self.layer_mult(kind.layer())
+            }
+            None => 0.0,
+        }
+    }
+
+    /// Damage multiplier against a layer, 0 for layers the turret can't target
+    pub fn layer_mult(&self, layer: Layer) -> f32 {
+        if !self.targets().hits(layer) {
+            return 0.0;
+        }
+        match (self, layer) {
+            // Blaster bursts are the dedicated anti-air
+            (Turret::Blaster, Layer::Air) => 1.5,
+            // The beam struggles to track fast flyers
+            (Turret::Laser, Layer::Air) => 0.5,
+            _ => 1.0,
+        }
+    }
+}
+
+/// Which enemy layers a turret can target
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TargetLayers {
+    Ground,
+    Air,
+    Both,
+    None,
+}
+
+impl TargetLayers {
+    pub fn hits(&self, layer: Layer) -> bool {
+        matches!(
+            (self, layer),
+            (TargetLayers::Both, _)
+                | (TargetLayers::Ground, Layer::Ground)
+                | (TargetLayers::Air, Layer::Air)
+        )
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TargetLayers::Ground => "GROUND",
+            TargetLayers::Air => "AIR",
+            TargetLayers::None => "NOTHING",
+            TargetLayers::Both => "GROUND+AIR",
         }
     }
 }
@@ -44,12 +162,30 @@ pub struct AttackDamage(pub f32);
 #[derive(Component, Deref, DerefMut)]
 pub struct Cooldown(pub Timer);
 
+/// The turret has attacked or paid out at least once, so it can no longer be undone
+#[derive(Component)]
+pub struct Fired;
+
 #[derive(Component, Deref, DerefMut)]
 pub struct Range(f32);
 
 #[derive(Component)]
 pub struct ContinuousLaserLight;
 
+/// Turrets are wrecked at zero health. The wreck no longer blocks its cell and can be repaired
+/// while the cell is still free
+#[derive(Component)]
+pub struct TurretHealth {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl TurretHealth {
+    pub fn new(max: f32) -> Self {
+        TurretHealth { current: max, max }
+    }
+}
+
 impl Turret {
     pub fn spawn_shockwave_turret(
         com: &mut Commands,
@@ -67,6 +203,7 @@ impl Turret {
                 TimerMode::Repeating,
             )))
             .insert(Range(4.0))
+            .insert(TurretHealth::new(Turret::Wave.max_health()))
             .insert(Turret::Wave);
         basic_light(
             &mut ecmds,
@@ -127,6 +264,7 @@ impl Turret {
                 TimerMode::Repeating,
             )))
             .insert(Range(10.0))
+            .insert(TurretHealth::new(Turret::Blaster.max_health()))
             .insert(Turret::Blaster);
         basic_light(
             &mut ecmds,
@@ -157,6 +295,41 @@ impl Turret {
         (Turret::Blaster, entity_id)
     }
 
+    pub fn spawn_economy_turret(
+        com: &mut Commands,
+        trans: Vec3,
+        model_assets: &ModelAssets,
+        pref: &Preferences,
+        settings: &GameSettings,
+    ) -> (Turret, bevy::prelude::Entity) {
+        let mut ecmds = com.spawn_empty();
+        let entity_id = ecmds.id();
+        ecmds
+            .insert(Income(settings.economy_turret_interval))
+            .insert(TurretHealth::new(Turret::Economy.max_health()))
+            .insert(Turret::Economy);
+        basic_light(
+            &mut ecmds,
+            Color::rgb(1.0, 0.8, 0.2),
+            80.0,
+            1.5 * pref.light_r,
+            0.5,
+            vec3(0.0, 0.6, 0.0),
+        );
+
+        // A small copy of the base, it's the base's supply line
+        ecmds.insert(HookedSceneBundle {
+            scene: SceneBundle {
+                scene: model_assets.base.clone(),
+                transform: Transform::from_translation(trans).with_scale(Vec3::splat(0.45)),
+                ..default()
+            },
+            hook: SceneHook::new(move |_entity, _cmds| {}),
+        });
+
+        (Turret::Economy, entity_id)
+    }
+
     pub fn spawn_laser_continuous_turret(
         com: &mut Commands,
         trans: Vec3,
@@ -172,6 +345,7 @@ impl Turret {
                 TimerMode::Once,
             )))
             .insert(Range(16.0))
+            .insert(TurretHealth::new(Turret::Laser.max_health()))
             .insert(Turret::Laser);
 
         ecmds.add_children(|parent| {
@@ -243,6 +417,7 @@ pub fn turret_fire(
             &Range,
             &mut Cooldown,
             &Turret,
+            Option<&Fired>,
         ),
         (
             Without<LaserBeam>,
@@ -251,7 +426,14 @@ pub fn turret_fire(
         ),
     >,
     mut enemies: Query<
-        (Entity, &Transform, &mut Health),
+        (
+            Entity,
+            &Transform,
+            &mut Health,
+            &EnemyKind,
+            &mut StatusEffects,
+            Option<&mut Steering>,
+        ),
         (With<Enemy>, Without<LaserBeam>, Without<DiamondLasers>),
     >,
     model_assets: Res<ModelAssets>,
@@ -268,18 +450,25 @@ pub fn turret_fire(
     player: Res<PlayerState>,
     pref: Res<Preferences>,
     mut audio_events: ResMut<AudioEvents>,
+    settings: Res<GameSettings>,
 ) {
     if !player.alive() {
         return;
     }
 
-    for (turret_entity, turret_trans, damage, range, mut cooldown, turret) in turrets.iter_mut() {
+    for (turret_entity, turret_trans, damage, range, mut cooldown, turret, fired) in
+        turrets.iter_mut()
+    {
         cooldown.tick(Duration::from_millis(TIMESTEP_MILLI));
+        let mut firing = false;
 
         let mut closest = None;
         let mut closest_dist = INFINITY;
 
-        for (entity, enemy_trans, _health) in enemies.iter_mut() {
+        for (entity, enemy_trans, _health, kind, _effects, _steering) in enemies.iter_mut() {
+            if !turret.targets().hits(kind.layer()) {
+                continue;
+            }
             let dist = turret_trans.translation.distance(enemy_trans.translation);
             if dist < closest_dist {
                 closest = Some(entity);
@@ -291,10 +480,16 @@ pub fn turret_fire(
             match turret {
                 Turret::Blaster => {
                     if let Some(entity) = closest {
-                        if let Ok((entity, enemy_trans, mut _health)) = enemies.get_mut(entity) {
+                        if let Ok((entity, enemy_trans, _health, _kind, _effects, steering)) =
+                            enemies.get_mut(entity)
+                        {
                             let dist = enemy_trans.translation.distance(turret_trans.translation);
                             if dist < **range {
                                 cooldown.reset();
+                                firing = true;
+                                if let Some(mut steering) = steering {
+                                    steering.targeted();
+                                }
                                 let turret_head_trans = turret_trans.translation + Vec3::Y * 1.0;
                                 let fire_dir =
                                     (enemy_trans.translation - turret_head_trans).normalize();
@@ -312,6 +507,7 @@ pub fn turret_fire(
                                     dest: enemy_trans.translation,
                                     enemy: entity,
                                     damage: damage.0,
+                                    source: *turret,
                                     blast_radius: 1.5,
                                     hit: false,
                                     hit_despawn_countdown: 1.0,
@@ -333,10 +529,24 @@ pub fn turret_fire(
                 }
                 Turret::Laser => {
                     if let Some(entity) = closest {
-                        if let Ok((_entity, enemy_trans, mut health)) = enemies.get_mut(entity) {
+                        if let Ok((_entity, enemy_trans, mut health, kind, mut effects, steering)) =
+                            enemies.get_mut(entity)
+                        {
                             if closest_dist < **range {
                                 //cooldown.reset(); Don't ever reset continuous
-                                health.0 -= damage.0 * TIMESTEP * player.laser_upgrade;
+                                firing = true;
+                                if let Some(mut steering) = steering {
+                                    steering.targeted();
+                                }
+                                health.0 -= damage.0
+                                    * TIMESTEP
+                                    * player.laser_upgrade
+                                    * turret.layer_mult(kind.layer())
+                                    * kind
+                                        .resistances(&settings)
+                                        .effectiveness(DamageType::Energy)
+                                    * effects.damage_taken_mult();
+                                effects.burn(LASER_BURN.0 * player.laser_upgrade, LASER_BURN.1);
                                 for (mut vis, laser) in diamond_lasers.iter_mut() {
                                     if laser.top_parent == turret_entity {
                                         vis.is_visible = true;
@@ -365,7 +575,12 @@ pub fn turret_fire(
                     }
                 }
                 Turret::Wave => {
-                    for (_entity, enemy_trans, mut health) in enemies.iter_mut() {
+                    for (_entity, enemy_trans, mut health, kind, mut effects, _steering) in
+                        enemies.iter_mut()
+                    {
+                        if !turret.targets().hits(kind.layer()) {
+                            continue;
+                        }
                         let dist = enemy_trans.translation.distance(turret_trans.translation);
                         if dist < **range {
                             for mut cap in caps.iter_mut() {
@@ -374,7 +589,15 @@ pub fn turret_fire(
                                 }
                             }
                             cooldown.reset();
-                            health.0 -= damage.0 * (1.0 / dist.max(1.0)) * player.wave_upgrade;
+                            firing = true;
+                            health.0 -= damage.0
+                                * (1.0 / dist.max(1.0))
+                                * player.wave_upgrade
+                                * turret.layer_mult(kind.layer())
+                                * kind.resistances(&settings).effectiveness(DamageType::Pulse)
+                                * effects.damage_taken_mult();
+                            effects.slow(WAVE_SLOW.0, WAVE_SLOW.1);
+                            effects.vulnerable(WAVE_VULNERABLE.0, WAVE_VULNERABLE.1);
                             let mut ecmds = com.spawn(SceneBundle {
                                 scene: model_assets.disc.clone(),
                                 transform: Transform::from_translation(
@@ -401,27 +624,59 @@ pub fn turret_fire(
                         }
                     }
                 }
+                // Has no cooldown, pays out in `generate_income`
+                Turret::Economy => (),
             }
         }
+        if firing && fired.is_none() {
+            com.entity(turret_entity).insert(Fired);
+        }
+    }
+}
+
+/// Seconds until an economy turret pays out again
+#[derive(Component, Deref, DerefMut)]
+pub struct Income(f32);
+
+pub fn generate_income(
+    mut com: Commands,
+    mut turrets: Query<(Entity, &mut Income), Without<Disabled>>,
+    mut player: ResMut<PlayerState>,
+    settings: Res<GameSettings>,
+) {
+    if !player.alive() {
+        return;
+    }
+    for (entity, mut income) in turrets.iter_mut() {
+        **income -= TIMESTEP;
+        if **income <= 0.0 {
+            **income += settings.economy_turret_interval;
+            player.credits += settings.economy_turret_income;
+            // A payout counts as firing, undoing after it would refund credits already earned
+            com.entity(entity).insert(Fired);
+        }
     }
 }
 
 pub fn blaster_point_at_enemy(
     mut turrets: Query<
-        (Entity, &mut Transform, &Range),
-        (With<Turret>, Without<Swivel>, Without<Disabled>),
+        (Entity, &mut Transform, &Range, &Turret),
+        (Without<Swivel>, Without<Disabled>),
     >,
     mut swivels: Query<(&mut Transform, &Swivel), Without<Turret>>,
-    mut enemies: Query<&Transform, (With<Enemy>, (Without<Turret>, Without<Swivel>))>,
+    mut enemies: Query<(&Transform, &EnemyKind), (With<Enemy>, (Without<Turret>, Without<Swivel>))>,
     player: Res<PlayerState>,
 ) {
     if !player.alive() {
         return;
     }
-    for (turret_entity, turret_trans, _range) in turrets.iter_mut() {
+    for (turret_entity, turret_trans, _range, turret) in turrets.iter_mut() {
         let mut closest = Vec3::ZERO;
         let mut closest_dist = INFINITY;
-        for enemy_trans in enemies.iter_mut() {
+        for (enemy_trans, kind) in enemies.iter_mut() {
+            if !turret.targets().hits(kind.layer()) {
+                continue;
+            }
             let dist = turret_trans.translation.distance(enemy_trans.translation);
             if dist < closest_dist {
                 closest = enemy_trans.translation;
@@ -452,21 +707,26 @@ pub fn blaster_point_at_enemy(
 pub fn progress_projectiles(
     mut com: Commands,
     mut projectiles: Query<(Entity, &mut Transform, &mut Projectile), Without<Enemy>>,
-    mut enemies: Query<(&Transform, &mut Health), With<Enemy>>,
+    mut enemies: Query<(&Transform, &mut Health, &EnemyKind, &mut StatusEffects), With<Enemy>>,
     model_assets: Res<ModelAssets>,
     player: Res<PlayerState>,
     pref: Res<Preferences>,
+    settings: Res<GameSettings>,
 ) {
     for (proj_entity, mut proj_trans, mut projectile) in projectiles.iter_mut() {
         proj_trans.translation += projectile.dir * projectile.speed;
 
         if proj_trans.translation.distance(projectile.dest) < 0.8 {
             projectile.hit = true;
-            for (enemy_trans, mut health) in enemies.iter_mut() {
+            for (enemy_trans, mut health, kind, mut effects) in enemies.iter_mut() {
                 if enemy_trans.translation.distance(proj_trans.translation)
                     < projectile.blast_radius
                 {
-                    **health -= projectile.damage * player.wave_upgrade;
+                    **health -= projectile.damage
+                        * player.wave_upgrade
+                        * projectile.source.effectiveness(*kind, &settings)
+                        * effects.damage_taken_mult();
+                    effects.stun(BLASTER_STUN);
                     if **health < 0.0 {
                         let mut ecmds = com.spawn(SceneBundle {
                             scene: model_assets.disc.clone(),
@@ -555,6 +815,8 @@ pub struct Projectile {
     pub dest: Vec3,
     pub enemy: Entity,
     pub damage: f32,
+    /// Turret that fired it, for damage type and layer rules
+    pub source: Turret,
     pub blast_radius: f32,
     pub hit: bool,
     pub hit_despawn_countdown: f32,
@@ -597,3 +859,33 @@ pub struct LaserBeam {
 
 #[derive(Component, Debug)]
 pub struct Disabled;
+
+/// Knocked out by enemies. Wrecked turrets are also `Disabled`, but unlike turrets disabled by
+/// the base going down they can be repaired.
+#[derive(Component, Debug)]
+pub struct Wrecked;
+
+pub fn wreck_destroyed_turrets(
+    mut com: Commands,
+    mut turrets: Query<(Entity, &mut Transform, &TurretHealth), (With<Turret>, Without<Wrecked>)>,
+    mut b: ResMut<GameBoard>,
+) {
+    for (entity, mut trans, health) in turrets.iter_mut() {
+        if health.current <= 0.0 {
+            trans.translation.y = -WRECK_SINK;
+            com.entity(entity).insert((Disabled, Wrecked));
+            b.wreck(entity);
+        }
+    }
+}
+
+pub fn repair_turret(
+    com: &mut Commands,
+    entity: Entity,
+    trans: &mut Transform,
+    health: &mut TurretHealth,
+) {
+    health.current = health.max;
+    trans.translation.y = 0.0;
+    com.entity(entity).remove::<(Disabled, Wrecked)>();
+}
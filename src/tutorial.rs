@@ -0,0 +1,267 @@
+use bevy::{math::*, prelude::*};
+use bevy_egui::{egui, EguiContext};
+use iyes_loopless::prelude::*;
+
+use crate::{
+    action::{Action, ActionQueue},
+    board::{Cell, GameBoard},
+    player::PlayerState,
+    turrets::Turret,
+    ui::panel_style,
+    GameState, PausedState,
+};
+
+pub struct TutorialPlugin;
+impl Plugin for TutorialPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.insert_resource(Tutorial::default())
+            .add_enter_system(GameState::RunLevel, setup_highlight_gfx)
+            .add_exit_system(GameState::RunLevel, end_tutorial)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::RunLevel)
+                    .after("INPUT")
+                    .after("UI PANELS")
+                    .with_system(advance_tutorial)
+                    .with_system(ui_tutorial)
+                    .with_system(update_highlight)
+                    .into(),
+            );
+    }
+}
+
+/// What a step waits for before moving on
+enum Goal {
+    /// The player has read it and clicked on. The game holds still meanwhile
+    Continue,
+    /// The highlighted cell ends up as asked, so only an action that went through counts
+    Cell(fn(&Cell) -> bool),
+    Player(fn(&PlayerState) -> bool),
+    /// The level timer runs out at least once
+    NextLevel,
+}
+
+struct Step {
+    text: &'static str,
+    highlight: Option<(u8, u8)>,
+    goal: Goal,
+}
+
+/// Beside the diagonal the first enemies take from the spawn to the base
+const TUTORIAL_CELL: (u8, u8) = (6, 5);
+
+static STEPS: [Step; 9] = [
+    Step {
+        text: "ENEMIES COME IN FROM THE FAR CORNER AND HEAD FOR YOUR BASE. EVERY ONE THAT REACHES \
+               IT COSTS HEALTH.",
+        highlight: None,
+        goal: Goal::Continue,
+    },
+    Step {
+        text: "PICK BLASTER IN THE BUILD BAR AT THE BOTTOM.",
+        highlight: None,
+        goal: Goal::Player(|player| player.turret_to_place == Some(Turret::Blaster)),
+    },
+    Step {
+        text: "NOW PLACE IT ON THE HIGHLIGHTED CELL.",
+        highlight: Some(TUTORIAL_CELL),
+        goal: Goal::Cell(|cell| matches!(cell.turret, Some((Turret::Blaster, _)))),
+    },
+    Step {
+        text: "GROUND ENEMIES PATH AROUND TURRETS AND WALLS, SO WHAT YOU BUILD IS A MAZE. A \
+               PLACEMENT THAT WOULD CUT OFF THE LAST PATH TO THE BASE IS REJECTED.",
+        highlight: None,
+        goal: Goal::Continue,
+    },
+    Step {
+        text: "FLYERS IGNORE THE MAZE AND HEAD STRAIGHT FOR THE BASE. PICK A TURRET TO SEE \
+               WHAT IT HITS, WAVES ONLY HIT THE GROUND.",
+        highlight: None,
+        goal: Goal::Continue,
+    },
+    Step {
+        text: "NEXT AT THE TOP COUNTS DOWN THE TEN SECONDS LEFT IN THIS LEVEL. EVERY LEVEL SENDS \
+               TOUGHER ENEMIES. WAIT FOR THE NEXT ONE.",
+        highlight: None,
+        goal: Goal::NextLevel,
+    },
+    Step {
+        text: "SELLING GIVES BACK HALF A TURRET'S COST. PICK SELL AND CLICK THE BLASTER.",
+        highlight: Some(TUTORIAL_CELL),
+        goal: Goal::Cell(|cell| cell.turret.is_none()),
+    },
+    Step {
+        text: "UPGRADES IN THE PANELS MAKE EVERY TURRET OF A KIND 5% STRONGER. BUY A BLASTER \
+               UPGRADE.",
+        highlight: None,
+        goal: Goal::Player(|player| player.blaster_upgrade > 1.0),
+    },
+    Step {
+        text: "THAT'S EVERYTHING. BUILD A MAZE AND SEE HOW FAR YOU GET.",
+        highlight: None,
+        goal: Goal::Continue,
+    },
+];
+
+/// Guided first game, stepping through `STEPS` alongside a normal run
+#[derive(Resource, Default)]
+pub struct Tutorial {
+    /// Index into `STEPS`, None when no tutorial is running
+    step: Option<usize>,
+    /// Level when the current step began
+    step_level: f32,
+    /// The tutorial paused the game for a `Goal::Continue` step, and unpauses it after
+    holding: bool,
+}
+
+impl Tutorial {
+    pub fn start(&mut self) {
+        self.step = Some(0);
+        self.step_level = 0.0;
+    }
+
+    fn current(&self) -> Option<&'static Step> {
+        self.step.map(|step| &STEPS[step])
+    }
+
+    fn advance(&mut self, player: &PlayerState) {
+        self.step = self
+            .step
+            .map(|step| step + 1)
+            .filter(|step| *step < STEPS.len());
+        self.step_level = player.level;
+    }
+}
+
+fn end_tutorial(mut tutorial: ResMut<Tutorial>) {
+    tutorial.step = None;
+    tutorial.holding = false;
+}
+
+fn advance_tutorial(
+    mut tutorial: ResMut<Tutorial>,
+    player: Res<PlayerState>,
+    b: Res<GameBoard>,
+    paused_state: Res<CurrentState<PausedState>>,
+    mut action_queue: ResMut<ActionQueue>,
+) {
+    // Paused through the queue like the player would, a pause the player made is left alone
+    let hold = matches!(
+        tutorial.current(),
+        Some(Step {
+            goal: Goal::Continue,
+            ..
+        })
+    );
+    let paused = *paused_state == CurrentState(PausedState::Paused);
+    if hold && !tutorial.holding && !paused {
+        action_queue.push(Action::GamePause);
+        tutorial.holding = true;
+    } else if !hold && tutorial.holding {
+        if paused {
+            action_queue.push(Action::GamePause);
+        }
+        tutorial.holding = false;
+    }
+
+    let step = match tutorial.current() {
+        Some(step) => step,
+        None => return,
+    };
+    let cell = &b.board[b.ls_to_idx(ivec2(TUTORIAL_CELL.0 as i32, TUTORIAL_CELL.1 as i32))];
+    let done = match step.goal {
+        Goal::Continue => false,
+        Goal::Cell(met) => met(cell),
+        Goal::Player(met) => met(&player),
+        Goal::NextLevel => player.level > tutorial.step_level,
+    };
+    if done {
+        tutorial.advance(&player);
+    }
+}
+
+fn ui_tutorial(
+    mut egui_context: ResMut<EguiContext>,
+    mut tutorial: ResMut<Tutorial>,
+    player: Res<PlayerState>,
+) {
+    let step = match tutorial.current() {
+        Some(step) => step,
+        None => return,
+    };
+    egui::Window::new("TUTORIAL")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 8.0))
+        .default_width(360.0)
+        .show(egui_context.ctx_mut(), |ui| {
+            panel_style(ui.style_mut(), false);
+            ui.label(step.text);
+            ui.horizontal(|ui| {
+                ui.label(&format!(
+                    "{}/{}",
+                    tutorial.step.unwrap_or_default() + 1,
+                    STEPS.len()
+                ));
+                if let Goal::Continue = step.goal {
+                    if ui.button("CONTINUE").clicked() {
+                        tutorial.advance(&player);
+                    }
+                }
+                if ui.button("SKIP").clicked() {
+                    tutorial.step = None;
+                }
+            });
+        });
+}
+
+#[derive(Component)]
+struct Highlight;
+
+#[derive(Resource)]
+struct HighlightGfx {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+fn setup_highlight_gfx(
+    mut com: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    com.insert_resource(HighlightGfx {
+        mesh: meshes.add(Mesh::from(shape::Box::new(1.0, 0.05, 1.0))),
+        material: materials.add(StandardMaterial {
+            base_color: Color::rgba(1.0, 0.9, 0.2, 0.5),
+            emissive: Color::rgb(0.6, 0.5, 0.1),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        }),
+    });
+}
+
+fn update_highlight(
+    mut com: Commands,
+    tutorial: Res<Tutorial>,
+    highlights: Query<Entity, With<Highlight>>,
+    b: Res<GameBoard>,
+    gfx: Res<HighlightGfx>,
+) {
+    if !tutorial.is_changed() {
+        return;
+    }
+    for entity in highlights.iter() {
+        com.entity(entity).despawn_recursive();
+    }
+    if let Some((x, y)) = tutorial.current().and_then(|step| step.highlight) {
+        let pos = b.ls_to_ws_vec3(ivec2(x as i32, y as i32));
+        com.spawn(PbrBundle {
+            mesh: gfx.mesh.clone(),
+            material: gfx.material.clone(),
+            transform: Transform::from_translation(pos + Vec3::Y * 0.03),
+            ..default()
+        })
+        .insert(Highlight);
+    }
+}
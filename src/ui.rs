@@ -2,42 +2,101 @@ use bevy::math::*;
 use bevy::prelude::*;
 use bevy_egui::egui::Color32;
 use bevy_egui::{egui::FontDefinitions, *};
-use iyes_loopless::prelude::ConditionSet;
-use lz4_flex::compress_prepend_size;
-use lz4_flex::decompress_size_prepended;
-use rkyv::Deserialize;
+use iyes_loopless::prelude::*;
 
 use crate::action::Action;
 use crate::action::ActionQueue;
-use crate::action::ActionRecording;
 use crate::action::GameRecorder;
 use crate::audio::AudioEvents;
 use crate::audio::MUSIC_LEVEL_CHANGED;
 use crate::audio::SFX_LEVEL_CHANGED;
+use crate::board::GameBoard;
+use crate::boss::Boss;
+use crate::camera::{CameraRig, CameraView};
+use crate::schedule::SimSpeed;
+use crate::schedule::TIMESTEP;
 
 use crate::GameState;
 
-use crate::{player::PlayerState, turrets::Turret};
+use crate::{
+    enemies::{EnemyKind, Health},
+    player::{GameSettings, Inspected, PlayerState},
+    turrets::{Turret, TurretHealth},
+};
 
 pub struct GameUI;
 impl Plugin for GameUI {
     fn build(&self, app: &mut bevy::prelude::App) {
         app.add_plugin(EguiPlugin)
             .insert_resource(Preferences::default())
+            .insert_resource(UiLayout::Wide)
+            .add_system(update_layout)
+            // Panels are laid out in order, each one taking its space from what's left
+            .add_system(ui_hud.run_in_state(GameState::RunLevel).label("UI HUD"))
+            .add_system(
+                ui_build_bar
+                    .run_in_state(GameState::RunLevel)
+                    .label("UI BUILD BAR")
+                    .after("UI HUD"),
+            )
+            .add_system(
+                ui_panels
+                    .run_in_state(GameState::RunLevel)
+                    .label("UI PANELS")
+                    .after("UI BUILD BAR"),
+            )
             .add_system_set(
                 ConditionSet::new()
                     .run_in_state(GameState::RunLevel)
-                    .with_system(ui_sidebar)
+                    .after("UI PANELS")
+                    .with_system(ui_inspect)
                     .into(),
             )
+            .add_system(detect_touch)
             .add_startup_system(setup_fonts);
     }
 }
 
+/// Logical pixels below which the sidebar switches to finger sized buttons
+const SMALL_SCREEN_WIDTH: f32 = 900.0;
+const FINGER_SIZE: f32 = 40.0;
+/// Windows this close to the 960x480 minimum get the compact layout
+const COMPACT_WIDTH: f32 = 1100.0;
+const COMPACT_HEIGHT: f32 = 600.0;
+/// Extra shrink on top of the preferred scale when space is short
+const COMPACT_SCALE: f32 = 0.8;
+const PANEL_WIDTH: f32 = 260.0;
+const FONT_SCALE: f32 = 1.5;
+const MIN_UI_SCALE: f32 = 0.5;
+const MAX_UI_SCALE: f32 = 2.0;
+const UI_SCALE_STEP: f32 = 0.1;
+
 const SELECTED_COLOR: Color32 = Color32::from_rgb(94 / 3, 255 / 3, 169 / 3);
 const DESELECTED_COLOR: Color32 = Color32::from_rgb(94 / 10, 255 / 10, 169 / 10);
 
-fn select_button(ui: &mut egui::Ui, text: &str, selected: bool) -> bool {
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UiLayout {
+    /// Panels docked on the right
+    Wide,
+    /// Near the minimum window size, panels hidden until asked for
+    Compact,
+    /// Taller than wide, panels stack under the build bar
+    Portrait,
+}
+
+impl UiLayout {
+    fn for_window(window: &Window) -> Self {
+        if window.height() > window.width() {
+            UiLayout::Portrait
+        } else if window.width() < COMPACT_WIDTH || window.height() < COMPACT_HEIGHT {
+            UiLayout::Compact
+        } else {
+            UiLayout::Wide
+        }
+    }
+}
+
+pub(crate) fn select_button(ui: &mut egui::Ui, text: &str, selected: bool) -> bool {
     ui.add(egui::Button::new(text).fill(if selected {
         SELECTED_COLOR
     } else {
@@ -46,183 +105,485 @@ fn select_button(ui: &mut egui::Ui, text: &str, selected: bool) -> bool {
     .clicked()
 }
 
-fn ui_sidebar(
+fn ui_hud(
     mut egui_context: ResMut<EguiContext>,
-    mut player: ResMut<PlayerState>,
-    mut windows: ResMut<Windows>,
+    player: Res<PlayerState>,
     mut pref: ResMut<Preferences>,
-    mut audio_events: ResMut<AudioEvents>,
-    mut action_queue: ResMut<ActionQueue>,
-    mut game_recorder: ResMut<GameRecorder>,
-    mut rec_string: Local<String>,
-    mut player_last_dead: Local<bool>,
+    bosses: Query<(&Health, &Boss)>,
+    settings: Res<GameSettings>,
+    windows: Res<Windows>,
 ) {
-    let mut player_died_this_frame = false;
-    if !*player_last_dead && !player.alive() {
-        player_died_this_frame = true;
-        *player_last_dead = true;
-    }
-
-    let window = windows.get_primary_mut().unwrap();
-    let my_frame = egui::containers::Frame {
-        fill: Color32::from_rgba_unmultiplied(0, 0, 0, 64),
-        stroke: egui::Stroke::NONE,
-        ..default()
-    };
-
-    egui::SidePanel::right("right_panel")
-        .frame(my_frame)
-        .resizable(false)
-        .min_width(window.width() * 0.17)
-        .default_width(window.width() * 0.17)
+    let finger_sized = pref.finger_sized(windows.get_primary().unwrap());
+    egui::TopBottomPanel::top("hud")
+        .frame(bar_frame())
         .show_separator_line(false)
         .show(egui_context.ctx_mut(), |ui| {
-            let mut style = ui.style_mut();
-            style.visuals.override_text_color = Some(Color32::from_rgb(94, 255, 169));
-            style.visuals.widgets.active.bg_fill = DESELECTED_COLOR;
-            style.visuals.widgets.inactive.bg_fill = DESELECTED_COLOR;
-            style.visuals.widgets.open.bg_fill = DESELECTED_COLOR;
-            style.visuals.widgets.hovered.bg_fill = SELECTED_COLOR;
-            ui.vertical_centered_justified(|ui| {
-                #[cfg(debug_assertions)]
-                {
-                    if ui.button("CREDITS").clicked() {
-                        action_queue.push(Action::CheatCredits);
-                    }
-                    if ui.button("HEALTH").clicked() {
-                        action_queue.push(Action::CheatHealth);
-                    }
-                    if ui.button("NEXT LEVEL").clicked() {
-                        action_queue.push(Action::CheatLevel);
-                    }
+            panel_style(ui.style_mut(), finger_sized);
+            ui.horizontal_wrapped(|ui| {
+                if select_button(ui, "PANELS", pref.show_panels) {
+                    pref.show_panels = !pref.show_panels;
                 }
                 let v = 1.0 - (player.level_time * 0.1 - player.level).fract();
-                ui.label(&format!(
-                    "LEVEL {}   NEXT {:.2}",
-                    player.level as u32,
-                    v * 10.0
-                ));
-                ui.label(&format!("HEALTH  {:8}", (player.health * 100.0) as u32));
-                ui.label(&format!("CREDITS {:8}", player.credits));
-                ui.label(&format!("KILLS   {:8}", player.kills));
+                ui.label(&format!("LEVEL {}", player.level as u32));
+                ui.label(&format!("NEXT {:.2}", v * 10.0));
+                ui.separator();
+                ui.label(&format!("HEALTH {}", player.health));
+                if player.shield > 0 {
+                    ui.label(&format!("SHIELD {}", player.shield));
+                }
+                ui.separator();
+                ui.label(&format!("CREDITS {}", player.credits));
+                ui.label(&format!("INTEREST {}", player.interest(&settings)));
+                ui.separator();
+                ui.label(&format!("KILLS {}", player.kills));
+                for (health, boss) in bosses.iter() {
+                    ui.separator();
+                    ui.label(&format!("BOSS {}", boss.phase.name()));
+                    ui.add(
+                        egui::ProgressBar::new((health.0 / boss.max_health).clamp(0.0, 1.0))
+                            .desired_width(120.0),
+                    );
+                }
+            });
+        });
+}
 
-                if player.health > 0.0 {
-                    ui.label("");
-                    ui.label("TURRETS");
-                    for (message, turret) in [
-                        ("BLASTER", Turret::Blaster),
-                        ("WAVE   ", Turret::Wave),
-                        ("LASER  ", Turret::Laser),
-                    ] {
-                        if select_button(
-                            ui,
-                            &format!("{} {:8}", message, turret.cost()),
-                            player.turret_to_place == Some(turret),
-                        ) {
-                            player.turret_to_place = Some(turret);
-                            player.sell_mode = false;
-                        }
+fn ui_build_bar(
+    mut egui_context: ResMut<EguiContext>,
+    mut player: ResMut<PlayerState>,
+    mut action_queue: ResMut<ActionQueue>,
+    sim_speed: Res<SimSpeed>,
+    settings: Res<GameSettings>,
+    pref: Res<Preferences>,
+    windows: Res<Windows>,
+) {
+    if !player.alive() {
+        return;
+    }
+    let finger_sized = pref.finger_sized(windows.get_primary().unwrap());
+    egui::TopBottomPanel::bottom("build_bar")
+        .frame(bar_frame())
+        .show_separator_line(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            panel_style(ui.style_mut(), finger_sized);
+            ui.horizontal_wrapped(|ui| {
+                for turret in [
+                    Turret::Blaster,
+                    Turret::Wave,
+                    Turret::Laser,
+                    Turret::Economy,
+                ] {
+                    if select_button(
+                        ui,
+                        &format!("{} {}", turret.name(), turret.cost()),
+                        player.turret_to_place == Some(turret),
+                    ) {
+                        player.turret_to_place = Some(turret);
+                        player.sell_mode = false;
+                        player.repair_mode = false;
+                        player.wall_mode = false;
+                        player.move_mode = false;
                     }
-                    if select_button(ui, "SELL", player.sell_mode) {
-                        player.sell_mode = !player.sell_mode;
-                        if player.sell_mode {
-                            player.turret_to_place = None;
-                        }
+                }
+                if select_button(
+                    ui,
+                    &format!("WALL {}", settings.wall_cost),
+                    player.wall_mode,
+                ) {
+                    player.wall_mode = !player.wall_mode;
+                    if player.wall_mode {
+                        player.turret_to_place = None;
+                        player.sell_mode = false;
+                        player.repair_mode = false;
+                        player.move_mode = false;
                     }
-                    ui.label("");
-                    ui.label("UPGRADES +5%");
-                    let cost = player.blaster_upgrade_cost();
-                    if ui.button(&format!("BLASTER {:8}", cost)).clicked() {
-                        action_queue.push(Action::BlasterUpgrade);
+                }
+                ui.separator();
+                if settings.allow_selling && select_button(ui, "SELL", player.sell_mode) {
+                    player.sell_mode = !player.sell_mode;
+                    if player.sell_mode {
+                        player.turret_to_place = None;
+                        player.repair_mode = false;
+                        player.wall_mode = false;
+                        player.move_mode = false;
                     }
-                    let cost = player.wave_upgrade_cost();
-                    if ui.button(&format!("WAVE    {:8}", cost)).clicked() {
-                        action_queue.push(Action::WaveUpgrade);
+                }
+                if select_button(ui, "REPAIR", player.repair_mode) {
+                    player.repair_mode = !player.repair_mode;
+                    if player.repair_mode {
+                        player.turret_to_place = None;
+                        player.sell_mode = false;
+                        player.wall_mode = false;
+                        player.move_mode = false;
                     }
-                    let cost = player.laser_upgrade_cost();
-                    if ui.button(&format!("LASERS  {:8}", cost)).clicked() {
-                        action_queue.push(Action::LaserUpgrade);
+                }
+                if select_button(
+                    ui,
+                    &format!("MOVE {}%", (settings.move_fee * 100.0).round()),
+                    player.move_mode,
+                ) {
+                    player.move_mode = !player.move_mode;
+                    if player.move_mode {
+                        player.turret_to_place = None;
+                        player.sell_mode = false;
+                        player.repair_mode = false;
+                        player.wall_mode = false;
                     }
-                    ui.label("");
+                }
+                if ui.button("UNDO").on_hover_text("CTRL+Z").clicked() {
+                    action_queue.push(Action::Undo);
+                }
+                ui.separator();
+                if sim_speed.max_speed {
+                    ui.label("SPEED MAX");
+                } else {
+                    ui.label(&format!("SPEED {:.2}", sim_speed.multiplier));
+                }
+                if ui.button(" -- ").clicked() {
+                    action_queue.push(Action::GameSpeedDec);
+                }
+                if ui.button(" ++ ").clicked() {
+                    action_queue.push(Action::GameSpeedInc);
+                }
+                if select_button(ui, "MAX", sim_speed.max_speed) {
+                    action_queue.push(Action::GameSpeedMax);
+                }
+                if ui.button("PAUSE").clicked() {
+                    action_queue.push(Action::GamePause);
+                }
+            });
+            if player.turret_to_place.is_some() || player.wall_mode {
+                ui.label("DRAG LINE, SHIFT AREA");
+            }
+        });
+}
 
-                    ui.label(&format!("GAME SPEED {:.2}", player.time_multiplier));
-                    ui.horizontal(|ui| {
-                        if ui.button(" -- ").clicked() {
-                            action_queue.push(Action::GameSpeedDec);
+/// Everything that doesn't need to be on screen all the time, as collapsible sections. Docked
+/// right on wide windows, under the build bar in portrait, and hidden behind PANELS when space is
+/// short
+fn ui_panels(
+    mut com: Commands,
+    mut egui_context: ResMut<EguiContext>,
+    player: Res<PlayerState>,
+    windows: Res<Windows>,
+    mut pref: ResMut<Preferences>,
+    mut audio_events: ResMut<AudioEvents>,
+    mut action_queue: ResMut<ActionQueue>,
+    game_recorder: Res<GameRecorder>,
+    mut camera_rigs: Query<&mut CameraRig>,
+    settings: Res<GameSettings>,
+    layout: Res<UiLayout>,
+) {
+    if !pref.show_panels {
+        return;
+    }
+
+    let finger_sized = pref.finger_sized(windows.get_primary().unwrap());
+    let mut contents = |ui: &mut egui::Ui| {
+        panel_style(ui.style_mut(), finger_sized);
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            ui.vertical_centered_justified(|ui| {
+                if player.alive() {
+                    if let Some(turret) = player.turret_to_place {
+                        egui::CollapsingHeader::new(turret.name())
+                            .default_open(true)
+                            .show(ui, |ui| turret_info(ui, turret, &settings));
+                    }
+                    egui::CollapsingHeader::new("UPGRADES +5%")
+                        .default_open(true)
+                        .show(ui, |ui| {
+                            for (name, cost, action) in [
+                                (
+                                    "BLASTER",
+                                    player.blaster_upgrade_cost(),
+                                    Action::BlasterUpgrade,
+                                ),
+                                ("WAVE", player.wave_upgrade_cost(), Action::WaveUpgrade),
+                                ("LASERS", player.laser_upgrade_cost(), Action::LaserUpgrade),
+                                ("SHIELD", settings.base_shield_cost, Action::BuyBaseShield),
+                            ] {
+                                if ui.button(&format!("{} {}", name, cost)).clicked() {
+                                    action_queue.push(action);
+                                }
+                            }
+                        });
+                }
+                egui::CollapsingHeader::new("GAME").show(ui, |ui| {
+                    #[cfg(debug_assertions)]
+                    {
+                        if ui.button("CREDITS").clicked() {
+                            action_queue.push(Action::CheatCredits);
                         }
-                        if ui.button(" ++ ").clicked() {
-                            action_queue.push(Action::GameSpeedInc);
+                        if ui.button("HEALTH").clicked() {
+                            action_queue.push(Action::CheatHealth);
                         }
-                        if ui.button("PAUSE").clicked() {
-                            action_queue.push(Action::GamePause);
+                        if ui.button("NEXT LEVEL").clicked() {
+                            action_queue.push(Action::CheatLevel);
                         }
-                    });
-                }
-                if ui
-                    .checkbox(&mut pref.less_lights, "REDUCE LIGHTS")
-                    .changed()
-                {
-                    if pref.less_lights {
-                        pref.light_r = 0.6;
-                    } else {
-                        pref.light_r = 1.0;
                     }
-                }
-                ui.horizontal(|ui| {
-                    if ui.button(" -- ").clicked() {
-                        pref.sfx = (pref.sfx - 0.1).max(0.0);
-                        **audio_events |= SFX_LEVEL_CHANGED;
+                    ui.label(&game_recorder.actions.run.label());
+                    ui.label(&game_recorder.actions.run.rules_label());
+                    if game_recorder.play {
+                        ui.label("WATCHING REPLAY");
                     }
-                    if ui.button(" ++ ").clicked() {
-                        pref.sfx = (pref.sfx + 0.1).min(3.0);
-                        **audio_events |= SFX_LEVEL_CHANGED;
+                    if ui.button("MAIN MENU").clicked() {
+                        com.insert_resource(NextState(GameState::MainMenu));
                     }
-                    ui.label(&format!("SFX {:.1}", pref.sfx));
                 });
-                ui.horizontal(|ui| {
-                    if ui.button(" -- ").clicked() {
-                        pref.music = (pref.music - 0.1).max(0.0);
-                        **audio_events |= MUSIC_LEVEL_CHANGED;
+                egui::CollapsingHeader::new("SETTINGS").show(ui, |ui| {
+                    for mut rig in camera_rigs.iter_mut() {
+                        ui.horizontal(|ui| {
+                            ui.label("VIEW");
+                            for (name, view) in [
+                                ("ISO 1", CameraView::Isometric),
+                                ("TOP 2", CameraView::TopDown),
+                            ] {
+                                if select_button(ui, name, rig.view == view) {
+                                    rig.set_view(view);
+                                }
+                            }
+                        });
                     }
-                    if ui.button(" ++ ").clicked() {
-                        pref.music = (pref.music + 0.1).min(3.0);
-                        **audio_events |= MUSIC_LEVEL_CHANGED;
+                    preference_controls(ui, &mut pref, &mut audio_events);
+                });
+                egui::CollapsingHeader::new("LEAKS").show(ui, |ui| {
+                    for leak in player.leaks.iter().rev().take(10) {
+                        let secs = leak.step as f32 * TIMESTEP;
+                        ui.label(&format!(
+                            "LV {} {:.1}S {} -{}",
+                            (secs / 10.0) as u32,
+                            secs,
+                            leak.kind.name(),
+                            leak.damage
+                        ));
                     }
-                    ui.label(&format!("MUSIC {:.1}", pref.music));
                 });
-                ui.label("");
-                if ui.button("RESTART GAME").clicked() {
-                    action_queue.push(Action::RestartGame);
-                    game_recorder.disable_rec = false;
-                    game_recorder.play = false;
-                    game_recorder.actions = ActionRecording::default();
-                }
-                if select_button(ui, "REPLAY", game_recorder.play) {
-                    action_queue.push(Action::RestartGame);
-                    game_recorder.play = true;
-                    game_recorder.disable_rec = true;
-                    game_recorder.play_head = 0;
-                }
-                if ui.text_edit_singleline(&mut *rec_string).changed() {
-                    if let Ok(compressed) = base64::decode(rec_string.trim()) {
-                        if let Ok(bytes) = decompress_size_prepended(&compressed) {
-                            if let Ok(archived) =
-                                rkyv::check_archived_root::<ActionRecording>(&bytes)
-                            {
-                                if let Ok(deserialized) =
-                                    archived.deserialize(&mut rkyv::Infallible)
-                                {
-                                    game_recorder.actions = deserialized;
-                                }
-                            }
-                        }
+            });
+        });
+    };
+
+    let ctx = egui_context.ctx_mut();
+    match *layout {
+        UiLayout::Portrait => {
+            egui::TopBottomPanel::bottom("panels")
+                .frame(bar_frame())
+                .resizable(false)
+                .max_height(ctx.screen_rect().height() * 0.5)
+                .show_separator_line(false)
+                .show(ctx, |ui| contents(ui));
+        }
+        UiLayout::Wide | UiLayout::Compact => {
+            egui::SidePanel::right("panels")
+                .frame(bar_frame())
+                .resizable(false)
+                .default_width(PANEL_WIDTH)
+                .show_separator_line(false)
+                .show(ctx, |ui| contents(ui));
+        }
+    }
+}
+
+/// Options that can change both from the settings screen and mid game
+pub(crate) fn preference_controls(
+    ui: &mut egui::Ui,
+    pref: &mut Preferences,
+    audio_events: &mut AudioEvents,
+) {
+    ui.checkbox(&mut pref.auto_pause, "AUTO PAUSE EACH LEVEL");
+    if ui
+        .checkbox(&mut pref.less_lights, "REDUCE LIGHTS")
+        .changed()
+    {
+        if pref.less_lights {
+            pref.light_r = 0.6;
+        } else {
+            pref.light_r = 1.0;
+        }
+    }
+    ui.horizontal(|ui| {
+        if ui.button(" -- ").clicked() {
+            pref.sfx = (pref.sfx - 0.1).max(0.0);
+            **audio_events |= SFX_LEVEL_CHANGED;
+        }
+        if ui.button(" ++ ").clicked() {
+            pref.sfx = (pref.sfx + 0.1).min(3.0);
+            **audio_events |= SFX_LEVEL_CHANGED;
+        }
+        ui.label(&format!("SFX {:.1}", pref.sfx));
+    });
+    ui.horizontal(|ui| {
+        if ui.button(" -- ").clicked() {
+            pref.music = (pref.music - 0.1).max(0.0);
+            **audio_events |= MUSIC_LEVEL_CHANGED;
+        }
+        if ui.button(" ++ ").clicked() {
+            pref.music = (pref.music + 0.1).min(3.0);
+            **audio_events |= MUSIC_LEVEL_CHANGED;
+        }
+        ui.label(&format!("MUSIC {:.1}", pref.music));
+    });
+    ui.horizontal(|ui| {
+        if ui.button(" -- ").clicked() {
+            pref.ui_scale = (pref.ui_scale - UI_SCALE_STEP).max(MIN_UI_SCALE);
+        }
+        if ui.button(" ++ ").clicked() {
+            pref.ui_scale = (pref.ui_scale + UI_SCALE_STEP).min(MAX_UI_SCALE);
+        }
+        ui.label(&format!("UI SCALE {:.1}", pref.ui_scale));
+    });
+}
+
+/// How hard a turret hits each enemy type
+fn turret_info(ui: &mut egui::Ui, turret: Turret, settings: &GameSettings) {
+    if turret == Turret::Economy {
+        ui.label(&format!(
+            "+{} EVERY {}S",
+            settings.economy_turret_income, settings.economy_turret_interval
+        ));
+        return;
+    }
+    if let Some(damage_type) = turret.damage_type() {
+        ui.label(&format!("{} DAMAGE", damage_type.name()));
+    }
+    ui.label(&format!("HITS {}", turret.targets().name()));
+    for kind in EnemyKind::ALL {
+        let effectiveness = turret.effectiveness(kind, settings);
+        let hint = if !turret.targets().hits(kind.layer()) {
+            "NONE"
+        } else if effectiveness < 0.5 {
+            "POOR"
+        } else if effectiveness < 1.0 {
+            "WEAK"
+        } else if effectiveness <= 1.0 {
+            "FULL"
+        } else {
+            "STRONG"
+        };
+        ui.label(&format!(
+            "{} {}% {}",
+            kind.name(),
+            (effectiveness * 100.0).round() as u32,
+            hint
+        ));
+    }
+}
+
+pub(crate) fn bar_frame() -> egui::Frame {
+    egui::Frame {
+        fill: Color32::from_rgba_unmultiplied(0, 0, 0, 64),
+        stroke: egui::Stroke::NONE,
+        inner_margin: egui::style::Margin::same(4.0),
+        ..default()
+    }
+}
+
+pub(crate) fn panel_style(style: &mut egui::Style, finger_sized: bool) {
+    style.visuals.override_text_color = Some(Color32::from_rgb(94, 255, 169));
+    style.visuals.widgets.active.bg_fill = DESELECTED_COLOR;
+    style.visuals.widgets.inactive.bg_fill = DESELECTED_COLOR;
+    style.visuals.widgets.open.bg_fill = DESELECTED_COLOR;
+    style.visuals.widgets.hovered.bg_fill = SELECTED_COLOR;
+    if finger_sized {
+        finger_spacing(style);
+    }
+}
+
+/// Picks the layout for the window shape and scales the whole UI to match
+fn update_layout(
+    windows: Res<Windows>,
+    mut layout: ResMut<UiLayout>,
+    mut pref: ResMut<Preferences>,
+    mut egui_settings: ResMut<EguiSettings>,
+) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let new_layout = UiLayout::for_window(window);
+    if *layout != new_layout {
+        *layout = new_layout;
+        // Panels start open only where they don't crowd the board
+        pref.show_panels = new_layout == UiLayout::Wide;
+    }
+    let fit = match new_layout {
+        UiLayout::Wide => 1.0,
+        UiLayout::Compact | UiLayout::Portrait => COMPACT_SCALE,
+    };
+    let scale = (pref.ui_scale * fit) as f64;
+    if egui_settings.scale_factor != scale {
+        egui_settings.scale_factor = scale;
+    }
+}
+
+fn finger_spacing(style: &mut egui::Style) {
+    style.spacing.interact_size.y = FINGER_SIZE;
+    style.spacing.button_padding = egui::vec2(8.0, 10.0);
+}
+
+fn detect_touch(touches: Res<Touches>, mut pref: ResMut<Preferences>) {
+    if !pref.large_buttons && touches.iter().next().is_some() {
+        pref.large_buttons = true;
+    }
+}
+
+/// Details and actions for the cell picked with a long press
+fn ui_inspect(
+    mut egui_context: ResMut<EguiContext>,
+    mut inspected: ResMut<Inspected>,
+    mut action_queue: ResMut<ActionQueue>,
+    b: Res<GameBoard>,
+    turrets: Query<(&Turret, &TurretHealth)>,
+    settings: Res<GameSettings>,
+    pref: Res<Preferences>,
+    windows: Res<Windows>,
+) {
+    let ls = match **inspected {
+        Some(ls) => ls,
+        None => return,
+    };
+    let finger_sized = pref.finger_sized(windows.get_primary().unwrap());
+    egui::Window::new("INSPECT")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(16.0, -16.0))
+        .show(egui_context.ctx_mut(), |ui| {
+            let style = ui.style_mut();
+            style.visuals.override_text_color = Some(Color32::from_rgb(94, 255, 169));
+            if finger_sized {
+                finger_spacing(style);
+            }
+            ui.vertical_centered_justified(|ui| {
+                let idx = b.ls_to_idx(ls);
+                let cell = &b.board[idx];
+                ui.label(&format!("CELL {},{}", ls.x, ls.y));
+                let turret = cell
+                    .turret
+                    .or(cell.wreck)
+                    .and_then(|(_, entity)| turrets.get(entity).ok());
+                if let Some((turret, health)) = turret {
+                    ui.label(turret.name());
+                    ui.label(&format!(
+                        "HEALTH {}%",
+                        ((health.current / health.max).max(0.0) * 100.0).round() as u32
+                    ));
+                    let cost = turret.repair_cost(health);
+                    if cost > 0 && ui.button(&format!("REPAIR {}", cost)).clicked() {
+                        action_queue.push(Action::RepairTurret(ls.x as u8, ls.y as u8));
                     }
+                } else if cell.wall.is_some() {
+                    ui.label("WALL");
+                } else if cell.obstacle {
+                    ui.label("OBSTACLE");
+                } else {
+                    ui.label("EMPTY");
                 }
-                if player_died_this_frame || ui.button("GET REPLAY STRING").clicked() {
-                    let bytes = rkyv::to_bytes::<_, 1024>(&game_recorder.actions).unwrap();
-                    let compressed = compress_prepend_size(&bytes);
-                    let base64_bytes = base64::encode(compressed);
-                    *rec_string = base64_bytes;
+                let sellable = cell.turret.is_some() || cell.wall.is_some();
+                if settings.allow_selling && sellable && ui.button("SELL").clicked() {
+                    action_queue.push(Action::SellTurret(ls.x as u8, ls.y as u8));
+                    **inspected = None;
+                }
+                if ui.button("CLOSE").clicked() {
+                    **inspected = None;
                 }
             });
         });
@@ -232,7 +593,7 @@ pub fn setup_fonts(mut egui_context: ResMut<EguiContext>) {
     let mut fonts = FontDefinitions::default();
 
     for (_text_style, mut data) in fonts.font_data.iter_mut() {
-        data.tweak.scale = 1.5;
+        data.tweak.scale = FONT_SCALE;
         data.font =
             std::borrow::Cow::Borrowed(include_bytes!("../assets/fonts/ShareTechMono-Regular.ttf"));
     }
@@ -245,6 +606,20 @@ pub struct Preferences {
     pub light_r: f32, //light range mult
     pub sfx: f64,
     pub music: f64,
+    /// Pause at every level boundary to plan the next one
+    pub auto_pause: bool,
+    /// Finger sized buttons, switched on the first time the screen is touched
+    pub large_buttons: bool,
+    /// Multiplies the size of the whole UI
+    pub ui_scale: f32,
+    /// Whether the collapsible panels are shown, reset to suit the layout when it changes
+    pub show_panels: bool,
+}
+
+impl Preferences {
+    pub fn finger_sized(&self, window: &Window) -> bool {
+        self.large_buttons || window.width() < SMALL_SCREEN_WIDTH
+    }
 }
 
 impl Default for Preferences {
@@ -254,6 +629,10 @@ impl Default for Preferences {
             light_r: 1.0,
             sfx: 1.0,
             music: 1.0,
+            auto_pause: false,
+            large_buttons: false,
+            ui_scale: 1.0,
+            show_panels: true,
         }
     }
 }
@@ -0,0 +1,22 @@
+use bevy::prelude::*;
+use bevy_scene_hook::{HookedSceneBundle, SceneHook};
+
+use crate::assets::ModelAssets;
+
+/// Cheap blocker for shaping the enemy path, fills a cell but has no attack and enemies don't
+/// target it
+#[derive(Component)]
+pub struct Wall;
+
+pub fn spawn_wall(com: &mut Commands, trans: Vec3, model_assets: &ModelAssets) -> Entity {
+    com.spawn(Wall)
+        .insert(HookedSceneBundle {
+            scene: SceneBundle {
+                scene: model_assets.wall.clone(),
+                transform: Transform::from_translation(trans),
+                ..default()
+            },
+            hook: SceneHook::new(move |_entity, _cmds| {}),
+        })
+        .id()
+}